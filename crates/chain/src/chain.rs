@@ -1,20 +1,32 @@
+use crate::deposition::{collect_deposition_requests, InputCell};
+use crate::error::ChainError;
+use crate::event::{ChainEvent, EventNotifier};
 use crate::next_block_context::NextBlockContext;
+use crate::signer::Signer;
 use crate::tx_pool::TxPool;
 use anyhow::{anyhow, Result};
 use ckb_types::{
     bytes::Bytes,
-    packed::{Script, Transaction, WitnessArgs, WitnessArgsReader},
+    packed::{Byte32, Script, Transaction, WitnessArgs, WitnessArgsReader},
     prelude::Unpack,
 };
+use crossbeam_channel::Receiver;
 use gw_common::{
-    h256_ext::H256Ext, merkle_utils::calculate_merkle_root, smt::Blake2bHasher, sparse_merkle_tree,
-    state::State, FINALIZE_BLOCKS, H256,
+    blake2b::new_blake2b,
+    h256_ext::H256Ext,
+    merkle_utils::{calculate_merkle_proof, calculate_merkle_root},
+    smt::{Blake2bHasher, Store as SMTStore},
+    sparse_merkle_tree,
+    state::{build_account_field_key, State, GW_ACCOUNT_SCRIPT_HASH},
+    FINALIZE_BLOCKS, H256,
 };
 use gw_config::ChainConfig;
 use gw_generator::{
-    generator::StateTransitionArgs, ChallengeContext, Error as GeneratorError, Generator, TxReceipt,
+    generator::{get_block_info, StateTransitionArgs},
+    traits::{CodeStore, StateExt},
+    ChallengeContext, Error as GeneratorError, Generator, RunResult, TxReceipt,
 };
-use gw_store::{Store, WrapStore};
+use gw_store::{OverlayStore, Store, UnlockableWithdrawal, WrapStore};
 use gw_types::{
     core::Status,
     packed::{
@@ -29,7 +41,14 @@ use gw_types::{
 };
 use parking_lot::Mutex;
 use std::time::SystemTime;
-use std::{convert::TryFrom, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    convert::TryFrom,
+    sync::Arc,
+};
+
+/// Max number of synced layer1 actions that can be reverted by a layer1 reorg
+const MAX_SYNC_CHECKPOINTS: usize = 1000;
 
 /// Produce block param
 pub struct ProduceBlockParam {
@@ -51,8 +70,8 @@ pub struct SyncParam {
 #[derive(Debug)]
 pub enum L1ActionContext {
     SubmitTxs {
-        /// deposition requests
-        deposition_requests: Vec<DepositionRequest>,
+        /// resolved input cells of the transaction, deposition requests are collected from them
+        input_cells: Vec<InputCell>,
     },
     Challenge {
         context: StartChallenge,
@@ -65,6 +84,17 @@ pub enum L1ActionContext {
     },
 }
 
+impl L1ActionContext {
+    fn name(&self) -> &'static str {
+        match self {
+            L1ActionContext::SubmitTxs { .. } => "submit txs",
+            L1ActionContext::Challenge { .. } => "challenge",
+            L1ActionContext::CancelChallenge { .. } => "cancel challenge",
+            L1ActionContext::Revert { .. } => "revert",
+        }
+    }
+}
+
 pub struct L1Action {
     /// transaction
     pub transaction: Transaction,
@@ -121,6 +151,7 @@ impl Eq for SyncEvent {}
 pub type StateStore = sparse_merkle_tree::default_store::DefaultStore<sparse_merkle_tree::H256>;
 pub type TxPoolImpl = TxPool<WrapStore<StateStore>>;
 
+#[derive(Clone)]
 pub struct LocalState {
     tip: L2Block,
     last_synced: HeaderInfo,
@@ -132,9 +163,9 @@ impl LocalState {
         &self.tip
     }
 
-    pub fn status(&self) -> Status {
+    pub fn status(&self) -> Result<Status, ChainError> {
         let status: u8 = self.last_global_state.status().into();
-        Status::try_from(status).expect("invalid status")
+        Status::try_from(status).map_err(ChainError::InvalidStatus)
     }
 
     pub fn last_synced(&self) -> &HeaderInfo {
@@ -146,13 +177,44 @@ impl LocalState {
     }
 }
 
+/// Chain states before a layer1 action is applied,
+/// used to rollback the chain when layer1 reorg
+struct SyncCheckpoint {
+    tx_hash: Byte32,
+    local_state: LocalState,
+    bad_block_context: Option<StartChallenge>,
+    pending_blocks: Vec<PendingBlock>,
+    /// blocks detached by a revert action, in ascending order
+    detached_blocks: Vec<L2Block>,
+    /// blocks marked as reverted by a revert action
+    reverted_block_hashes: Vec<H256>,
+}
+
+/// A layer2 block which is not applied to the local state,
+/// it is the bad block or a block built on the bad block.
+#[derive(Clone)]
+struct PendingBlock {
+    l2block: L2Block,
+    header_info: HeaderInfo,
+    deposition_requests: Vec<DepositionRequest>,
+}
+
 pub struct Chain {
     pub rollup_type_script_hash: [u8; 32],
+    /// scripts to recognize deposition cells
+    deposition_lock: Script,
+    l1_sudt_type: Script,
     pub store: Store<StateStore>,
     pub bad_block_context: Option<StartChallenge>,
+    /// the bad block and blocks submitted on top of it, they are applied or reverted by the challenge result
+    pending_blocks: Vec<PendingBlock>,
     pub local_state: LocalState,
     pub generator: Generator,
     pub tx_pool: Arc<Mutex<TxPoolImpl>>,
+    /// signer of the aggregator, required to produce blocks
+    signer: Option<Signer>,
+    sync_checkpoints: VecDeque<SyncCheckpoint>,
+    event_notifier: EventNotifier,
 }
 
 impl Chain {
@@ -161,6 +223,7 @@ impl Chain {
         store: Store<StateStore>,
         generator: Generator,
         tx_pool: Arc<Mutex<TxPoolImpl>>,
+        signer: Option<Signer>,
     ) -> Result<Self> {
         let rollup_type_script: Script = config.rollup_type_script.clone().into();
         let rollup_type_script_hash = rollup_type_script.calc_script_hash().unpack();
@@ -179,10 +242,16 @@ impl Chain {
         Ok(Chain {
             store,
             bad_block_context: None,
+            pending_blocks: Vec::new(),
             local_state,
             generator,
             tx_pool,
             rollup_type_script_hash,
+            deposition_lock: config.deposition_lock,
+            l1_sudt_type: config.l1_sudt_type,
+            signer,
+            sync_checkpoints: VecDeque::with_capacity(MAX_SYNC_CHECKPOINTS),
+            event_notifier: EventNotifier::default(),
         })
     }
 
@@ -195,120 +264,722 @@ impl Chain {
         &self.store
     }
 
+    /// Take the withdrawals which become unlockable via finalize since the last call
+    pub fn take_unlockable_withdrawals(&mut self) -> Vec<UnlockableWithdrawal> {
+        self.store.take_unlockable_withdrawals()
+    }
+
+    /// Subscribe chain events
+    ///
+    /// The receiver should be consumed in time, otherwise the new events are dropped.
+    pub fn subscribe(&self) -> Receiver<ChainEvent> {
+        self.event_notifier.subscribe()
+    }
+
     /// Sync chain from layer1
+    ///
+    /// A failed action is rolled back and the actions applied before it are kept,
+    /// a retry with the same param skips the applied actions.
     pub fn sync(&mut self, param: SyncParam) -> Result<SyncEvent> {
-        // TODO handle layer1 reorg
+        // revert layer1 actions which are abandoned by layer1 reorg
         if !param.reverts.is_empty() {
-            panic!("layer1 chain has forked!")
+            self.revert_l1_actions(&param.reverts)?;
         }
         // apply tx to state
+        let mut event = SyncEvent::Success;
         for action in param.updates {
-            let L1Action {
-                transaction,
-                header_info,
-                context,
-            } = action;
-            let global_state = parse_global_state(&transaction, &self.rollup_type_script_hash)?;
-            assert!(
-                {
-                    let number: u64 = header_info.number().unpack();
-                    number
-                } >= {
-                    let number: u64 = self.local_state.last_synced.number().unpack();
-                    number
-                },
-                "must be greater than or equalled to last synced number"
-            );
-            let status = {
-                let status: u8 = self.local_state.last_global_state.status().into();
-                Status::try_from(status).expect("invalid status")
-            };
-            let event = match (status, context) {
-                (
-                    Status::Running,
-                    L1ActionContext::SubmitTxs {
-                        deposition_requests,
-                    },
-                ) => {
-                    // Submit transactions
-                    // parse layer2 block
-                    let l2block = parse_l2block(&transaction, &self.rollup_type_script_hash)?;
-                    if let Some(challenge_context) = self.process_block(
-                        l2block.clone(),
-                        header_info.clone(),
-                        deposition_requests,
-                    )? {
-                        // stop syncing and return event
-                        self.bad_block_context = Some(challenge_context.args.clone());
-                        SyncEvent::BadBlock(challenge_context)
-                    } else {
-                        SyncEvent::Success
+            let tx_hash = action.transaction.calc_tx_hash();
+            if self.is_synced(&tx_hash) {
+                eprintln!("skip synced layer1 action, tx {}", tx_hash);
+                continue;
+            }
+            let mut checkpoint = self.new_sync_checkpoint(tx_hash);
+            match self.sync_l1_action(action, &mut checkpoint) {
+                Ok(action_event) => {
+                    self.push_sync_checkpoint(checkpoint);
+                    // stop syncing and return to caller if any event happen
+                    if action_event != SyncEvent::Success {
+                        event = action_event;
+                        break;
                     }
                 }
-                (Status::Running, L1ActionContext::Challenge { context }) => {
-                    // Challenge
-                    let status: u8 = global_state.status().into();
-                    assert_eq!(Status::try_from(status), Ok(Status::Halting));
-                    if let Some(current_bad_block) = self.bad_block_context.as_ref() {
-                        if current_bad_block.as_slice() == context.as_slice() {
-                            // bad block is in challenge, just wait.
-                            return Ok(SyncEvent::WaitChallenge);
-                        }
-                        SyncEvent::WaitChallenge
-                    } else {
-                        // now, either we haven't found a bad block or the challenge is challenge a validate block
-                        // in both cases the challenge is bad
-                        // TODO: implement this
-                        let _witness = CancelChallenge::default();
-                        let _tx_receipt = unimplemented!();
-                        // SyncEvent::BadChallenge {
-                        //     witness,
-                        //     tx_receipt,
-                        // }
+                Err(err) => {
+                    // rollback the partially applied action
+                    if let Err(rollback_err) = self.rollback_sync_checkpoints(vec![checkpoint]) {
+                        return Err(anyhow!(
+                            "failed to rollback layer1 action, error: {}, sync error: {}",
+                            rollback_err,
+                            err
+                        ));
                     }
+                    return Err(err);
                 }
-                (Status::Halting, L1ActionContext::CancelChallenge { context: _ }) => {
-                    // TODO update states
-                    let status: u8 = global_state.status().into();
-                    assert_eq!(Status::try_from(status), Ok(Status::Running));
+            }
+        }
+        // update tx pool state
+        let overlay_state = self.store.new_overlay()?;
+        self.tx_pool.lock().update_tip(
+            &self.local_state.tip,
+            overlay_state,
+            param.next_block_context,
+        )?;
+        Ok(event)
+    }
+
+    /// Apply a layer1 action
+    ///
+    /// `checkpoint` is the chain states before the action, it's pushed by the caller on success.
+    fn sync_l1_action(
+        &mut self,
+        action: L1Action,
+        checkpoint: &mut SyncCheckpoint,
+    ) -> Result<SyncEvent> {
+        let L1Action {
+            transaction,
+            header_info,
+            context,
+        } = action;
+        let global_state = parse_global_state(&transaction, &self.rollup_type_script_hash)?;
+        let number: u64 = header_info.number().unpack();
+        let last_synced: u64 = self.local_state.last_synced.number().unpack();
+        if number < last_synced {
+            return Err(ChainError::OutOfOrderHeader {
+                number,
+                last_synced,
+            }
+            .into());
+        }
+        let status = self.local_state.status()?;
+        let post_status = {
+            let status: u8 = global_state.status().into();
+            Status::try_from(status).map_err(ChainError::InvalidStatus)?
+        };
+        let event = match (status, context) {
+            (Status::Running, L1ActionContext::SubmitTxs { input_cells }) => {
+                // Submit transactions
+                check_status_transition("submit txs", Status::Running, post_status)?;
+                // parse layer2 block
+                let l2block = parse_l2block(&transaction, &self.rollup_type_script_hash)?;
+                let deposition_requests = collect_deposition_requests(
+                    &transaction,
+                    &input_cells,
+                    &self.rollup_type_script_hash,
+                    &self.deposition_lock,
+                    &self.l1_sudt_type,
+                )?;
+                let pending_block = PendingBlock {
+                    l2block: l2block.clone(),
+                    header_info: header_info.clone(),
+                    deposition_requests: deposition_requests.clone(),
+                };
+                if self.bad_block_context.is_some() {
+                    // the block is built on the bad block, wait for the challenge result
+                    self.pending_blocks.push(pending_block);
+                    SyncEvent::Success
+                } else if let Some(challenge_context) =
+                    self.process_block(l2block, header_info.clone(), deposition_requests)?
+                {
+                    // stop syncing and return event
+                    self.bad_block_context = Some(challenge_context.args.clone());
+                    self.pending_blocks.push(pending_block);
+                    SyncEvent::BadBlock(challenge_context)
+                } else {
                     SyncEvent::Success
                 }
-                (Status::Halting, L1ActionContext::Revert { context }) => {
-                    // TODO revert layer2 status
-                    let status: u8 = global_state.status().into();
-                    assert_eq!(Status::try_from(status), Ok(Status::Running));
-                    assert_eq!(
-                        self.bad_block_context.as_ref().map(|b| b.as_slice()),
-                        Some(context.as_slice()),
-                        "revert from the bad block"
+            }
+            (Status::Running, L1ActionContext::Challenge { context }) => {
+                // Challenge
+                check_status_transition("challenge", Status::Halting, post_status)?;
+                self.event_notifier.notify(ChainEvent::ChallengeStarted {
+                    context: context.clone(),
+                });
+                if self.bad_block_context.is_some() {
+                    // bad block is in challenge, just wait.
+                    SyncEvent::WaitChallenge
+                } else {
+                    // now, either we haven't found a bad block or the challenge is challenge a validate block
+                    // in both cases the challenge is bad, re-execute the tx to build the witness
+                    match self.build_cancel_challenge(&context)? {
+                        Some((witness, tx_receipt)) => SyncEvent::BadChallenge {
+                            witness,
+                            tx_receipt,
+                        },
+                        None => {
+                            // the challenged tx is failed to execute, the challenge is valid
+                            self.bad_block_context = Some(context);
+                            SyncEvent::WaitChallenge
+                        }
+                    }
+                }
+            }
+            (Status::Halting, L1ActionContext::CancelChallenge { context }) => {
+                // Cancel challenge
+                check_status_transition("cancel challenge", Status::Running, post_status)?;
+                self.event_notifier.notify(ChainEvent::ChallengeCancelled {
+                    context: context.clone(),
+                });
+                let cancelled_block_hash = context.raw_l2block().hash().pack();
+                let is_our_challenge = self
+                    .bad_block_context
+                    .as_ref()
+                    .map(|c| c.block_hash().as_slice() == cancelled_block_hash.as_slice())
+                    .unwrap_or(false);
+                if is_our_challenge {
+                    eprintln!(
+                        "challenge is cancelled, block {} is valid",
+                        cancelled_block_hash
+                    );
+                    self.bad_block_context = None;
+                    // apply the blocks which are blocked by the challenge
+                    match self.process_pending_blocks(&context.raw_l2block().hash())? {
+                        Some(challenge_context) => SyncEvent::BadBlock(challenge_context),
+                        None => SyncEvent::Success,
+                    }
+                } else {
+                    eprintln!(
+                        "challenge is cancelled, block {} is not challenged by us",
+                        cancelled_block_hash
                     );
                     SyncEvent::Success
                 }
-                (status, context) => {
-                    panic!(
-                        "unsupported syncing state: status {:?} context {:?}",
-                        status, context
+            }
+            (Status::Halting, L1ActionContext::Revert { context }) => {
+                // Revert layer2 status
+                check_status_transition("revert", Status::Running, post_status)?;
+                // layer1 is authoritative, follow the revert even if we found another bad block
+                if self.bad_block_context.as_ref().map(|b| b.as_slice()) != Some(context.as_slice())
+                {
+                    let block_hash: [u8; 32] = context.block_hash().unpack();
+                    eprintln!(
+                        "revert block {} which is not the bad block found by us",
+                        block_hash.pack()
                     );
                 }
-            };
-
-            // update last global state
-            self.local_state.last_global_state = global_state.clone();
-            self.local_state.last_synced = header_info;
-            self.store.set_tip_global_state(global_state)?;
-            // return to caller if any event happen
-            if event != SyncEvent::Success {
-                return Ok(event);
+                let (detached_blocks, reverted_block_hashes) =
+                    self.revert_bad_block(&context, &global_state)?;
+                // record the reverted blocks, so the revert can be rolled back when layer1 reorg
+                checkpoint.detached_blocks = detached_blocks;
+                checkpoint.reverted_block_hashes = reverted_block_hashes;
+                SyncEvent::Success
+            }
+            (status, context) => {
+                return Err(ChainError::UnsupportedSyncState {
+                    status,
+                    action: context.name(),
+                }
+                .into());
             }
+        };
+
+        // update last global state
+        self.local_state.last_global_state = global_state.clone();
+        self.local_state.last_synced = header_info;
+        self.store.set_tip_global_state(global_state)?;
+        let post_status = self.local_state.status()?;
+        if post_status != status {
+            self.event_notifier.notify(ChainEvent::StatusChanged {
+                status: post_status,
+            });
         }
-        // update tx pool state
-        let overlay_state = self.store.new_overlay()?;
-        self.tx_pool.lock().update_tip(
-            &self.local_state.tip,
-            overlay_state,
-            param.next_block_context,
+        Ok(event)
+    }
+
+    /// Whether the layer1 action is synced and not reverted
+    fn is_synced(&self, tx_hash: &Byte32) -> bool {
+        self.sync_checkpoints
+            .iter()
+            .any(|checkpoint| checkpoint.tx_hash.as_slice() == tx_hash.as_slice())
+    }
+
+    fn new_sync_checkpoint(&self, tx_hash: Byte32) -> SyncCheckpoint {
+        SyncCheckpoint {
+            tx_hash,
+            local_state: self.local_state.clone(),
+            bad_block_context: self.bad_block_context.clone(),
+            pending_blocks: self.pending_blocks.clone(),
+            detached_blocks: Vec::new(),
+            reverted_block_hashes: Vec::new(),
+        }
+    }
+
+    fn push_sync_checkpoint(&mut self, checkpoint: SyncCheckpoint) {
+        if self.sync_checkpoints.len() >= MAX_SYNC_CHECKPOINTS {
+            self.sync_checkpoints.pop_front();
+        }
+        self.sync_checkpoints.push_back(checkpoint);
+    }
+
+    /// Revert layer1 actions
+    ///
+    /// `reverts` contains actions from the tip to the fork point,
+    /// the chain is rolled back to the state before the last action.
+    fn revert_l1_actions(&mut self, reverts: &[L1Action]) -> Result<()> {
+        // the reverts are applied by a previous sync which is failed at the updates
+        if reverts
+            .iter()
+            .all(|action| !self.is_synced(&action.transaction.calc_tx_hash()))
+        {
+            eprintln!("skip reverted layer1 actions, count {}", reverts.len());
+            return Ok(());
+        }
+        if reverts.len() > self.sync_checkpoints.len() {
+            return Err(anyhow!(
+                "layer1 reorg is too deep to revert, reverts {} checkpoints {}",
+                reverts.len(),
+                self.sync_checkpoints.len()
+            ));
+        }
+        for (checkpoint, action) in self.sync_checkpoints.iter().rev().zip(reverts) {
+            let tx_hash = action.transaction.calc_tx_hash();
+            if checkpoint.tx_hash.as_slice() != tx_hash.as_slice() {
+                return Err(anyhow!(
+                    "reverted action is not synced, expected tx {} actual tx {}",
+                    checkpoint.tx_hash,
+                    tx_hash
+                ));
+            }
+        }
+        let fork_point_index = self.sync_checkpoints.len() - reverts.len();
+        let checkpoints: Vec<SyncCheckpoint> =
+            self.sync_checkpoints.drain(fork_point_index..).collect();
+        self.rollback_sync_checkpoints(checkpoints)
+    }
+
+    /// Rollback the chain to the first checkpoint
+    ///
+    /// `checkpoints` are in the synced order.
+    fn rollback_sync_checkpoints(&mut self, checkpoints: Vec<SyncCheckpoint>) -> Result<()> {
+        // remove the reverted marks, the newest revert first
+        for checkpoint in checkpoints.iter().rev() {
+            self.store
+                .unrevert_blocks(&checkpoint.reverted_block_hashes)?;
+        }
+        let mut checkpoints = checkpoints.into_iter();
+        let SyncCheckpoint {
+            local_state,
+            bad_block_context,
+            pending_blocks,
+            detached_blocks,
+            ..
+        } = match checkpoints.next() {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(()),
+        };
+
+        // blocks of the fork point which are detached by revert actions,
+        // the first detached block of a number is the one attached at the fork point
+        let fork_tip_number: u64 = local_state.tip.raw().number().unpack();
+        let mut fork_blocks: BTreeMap<u64, L2Block> = BTreeMap::new();
+        for block in detached_blocks
+            .into_iter()
+            .chain(checkpoints.flat_map(|checkpoint| checkpoint.detached_blocks))
+        {
+            let number: u64 = block.raw().number().unpack();
+            if number <= fork_tip_number {
+                fork_blocks.entry(number).or_insert(block);
+            }
+        }
+        let detach_to_number = fork_blocks
+            .keys()
+            .next()
+            .map(|number| number - 1)
+            .unwrap_or(fork_tip_number);
+
+        // detach reverted blocks, the account state is restored to the fork point
+        loop {
+            let tip = self
+                .store
+                .get_tip_block()?
+                .ok_or_else(|| anyhow!("can't find tip from store"))?;
+            let tip_number: u64 = tip.raw().number().unpack();
+            if tip_number <= detach_to_number {
+                break;
+            }
+            self.store.detach_block(&tip)?;
+            self.event_notifier
+                .notify(ChainEvent::BlockDetached { block: tip });
+        }
+        // re-attach the blocks detached by revert actions
+        for block in fork_blocks.into_iter().map(|(_number, block)| block) {
+            self.reattach_block(block)?;
+        }
+        if self.store.get_tip_block()?.map(|b| b.hash()) != Some(local_state.tip.hash()) {
+            return Err(anyhow!("can't restore tip block to the fork point"));
+        }
+
+        self.store
+            .set_tip_global_state(local_state.last_global_state.clone())?;
+        let status = self.local_state.status()?;
+        self.local_state = local_state;
+        let post_status = self.local_state.status()?;
+        if post_status != status {
+            self.event_notifier.notify(ChainEvent::StatusChanged {
+                status: post_status,
+            });
+        }
+        self.bad_block_context = bad_block_context;
+        self.pending_blocks = pending_blocks;
+        Ok(())
+    }
+
+    /// Attach a block which is detached by a revert action
+    ///
+    /// The state journal of the block is removed when it's detached,
+    /// so the block is re-executed to record the state changes again.
+    fn reattach_block(&mut self, l2block: L2Block) -> Result<()> {
+        let block_hash: H256 = l2block.hash().into();
+        let deposition_requests = self
+            .store
+            .get_block_deposition_requests(&block_hash)?
+            .ok_or_else(|| anyhow!("can't find deposition requests of the detached block"))?;
+        let args = StateTransitionArgs {
+            l2block: l2block.clone(),
+            deposition_requests,
+        };
+        self.store.begin_state_journal();
+        // the block is accepted by layer1 before it's detached
+        if let Err(err) = self
+            .generator
+            .apply_accepted_state_transition(&mut self.store, args)
+        {
+            self.store.rollback_state_journal()?;
+            let number: u64 = l2block.raw().number().unpack();
+            return Err(anyhow!(
+                "failed to re-attach block {}, error: {}",
+                number,
+                err
+            ));
+        }
+        self.store.attach_block(l2block.clone())?;
+        self.event_notifier
+            .notify(ChainEvent::NewTip { block: l2block });
+        Ok(())
+    }
+
+    /// Revert the bad block and its descendants
+    ///
+    /// The account state is rolled back to the state before the bad block,
+    /// the reverted blocks are recorded in the reverted block SMT.
+    /// The reverted block root is verified before the store is modified.
+    ///
+    /// Returns the detached blocks in ascending order and the newly reverted block hashes.
+    fn revert_bad_block(
+        &mut self,
+        context: &StartChallenge,
+        global_state: &GlobalState,
+    ) -> Result<(Vec<L2Block>, Vec<H256>)> {
+        let bad_block_hash: [u8; 32] = context.block_hash().unpack();
+        let bad_block_hash: H256 = bad_block_hash.into();
+
+        // collect the bad block and its descendants
+        let attached_bad_block = match self.store.get_block(&bad_block_hash)? {
+            Some(block) => {
+                let number: u64 = block.raw().number().unpack();
+                if self.store.get_block_hash_by_number(number)? == Some(bad_block_hash) {
+                    Some(number)
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
+        let mut reverted_blocks = Vec::new();
+        let pending_index = match attached_bad_block {
+            Some(bad_block_number) => {
+                let tip_number: u64 = self.local_state.tip.raw().number().unpack();
+                for number in bad_block_number..=tip_number {
+                    let block_hash = self
+                        .store
+                        .get_block_hash_by_number(number)?
+                        .ok_or_else(|| anyhow!("can't find block {}", number))?;
+                    reverted_blocks.push(block_hash);
+                }
+                // all pending blocks are built on the attached blocks
+                Some(0)
+            }
+            None => self.pending_blocks.iter().position(|pending_block| {
+                H256::from(pending_block.l2block.hash()) == bad_block_hash
+            }),
+        };
+        match pending_index {
+            Some(index) => reverted_blocks.extend(
+                self.pending_blocks[index..]
+                    .iter()
+                    .map(|pending_block| H256::from(pending_block.l2block.hash())),
+            ),
+            None => reverted_blocks.push(bad_block_hash),
+        }
+
+        // the reverted block root must be consistent with layer1
+        let mut newly_reverted_blocks = Vec::with_capacity(reverted_blocks.len());
+        for block_hash in &reverted_blocks {
+            if !self.store.is_block_reverted(block_hash)? {
+                newly_reverted_blocks.push(*block_hash);
+            }
+        }
+        let local_reverted_block_root: [u8; 32] = if newly_reverted_blocks.is_empty() {
+            (*self.store.reverted_block_smt().root()).into()
+        } else {
+            let prev_leaves = newly_reverted_blocks
+                .iter()
+                .map(|block_hash| (*block_hash, H256::zero()))
+                .collect();
+            let post_leaves = newly_reverted_blocks
+                .iter()
+                .map(|block_hash| (*block_hash, H256::one()))
+                .collect();
+            self.store
+                .reverted_block_smt()
+                .merkle_proof(newly_reverted_blocks.clone())?
+                .compile(prev_leaves)?
+                .compute_root::<Blake2bHasher>(post_leaves)?
+                .into()
+        };
+        let reverted_block_root: [u8; 32] = global_state.reverted_block_root().unpack();
+        if reverted_block_root != local_reverted_block_root {
+            return Err(anyhow!(
+                "reverted block root mismatch, layer1 {} local {}",
+                reverted_block_root.pack(),
+                local_reverted_block_root.pack()
+            ));
+        }
+
+        // detach the bad block and its descendants if they are attached
+        let mut detached_blocks = Vec::new();
+        if let Some(bad_block_number) = attached_bad_block {
+            loop {
+                let tip = self
+                    .store
+                    .get_tip_block()?
+                    .ok_or_else(|| anyhow!("can't find tip from store"))?;
+                let tip_number: u64 = tip.raw().number().unpack();
+                if tip_number < bad_block_number {
+                    break;
+                }
+                self.store.detach_block(&tip)?;
+                detached_blocks.push(tip);
+            }
+            detached_blocks.reverse();
+        }
+        if let Some(index) = pending_index {
+            self.pending_blocks.truncate(index);
+        }
+        self.store.revert_blocks(&newly_reverted_blocks)?;
+
+        self.local_state.tip = self
+            .store
+            .get_tip_block()?
+            .ok_or_else(|| anyhow!("can't find tip from store"))?;
+        let is_bad_block_reverted = self
+            .bad_block_context
+            .as_ref()
+            .map(|c| {
+                let block_hash: [u8; 32] = c.block_hash().unpack();
+                reverted_blocks.contains(&block_hash.into())
+            })
+            .unwrap_or(false);
+        if is_bad_block_reverted {
+            self.bad_block_context = None;
+        }
+        self.event_notifier.notify(ChainEvent::BlockReverted {
+            block_hashes: reverted_blocks,
+        });
+        Ok((detached_blocks, newly_reverted_blocks))
+    }
+
+    /// Apply the pending blocks after the challenge is cancelled
+    ///
+    /// The cancelled block is accepted by layer1, it's applied even if a tx is failed locally.
+    /// The following blocks are processed as usual, returns the challenge context
+    /// if another bad block is found, the blocks since the bad block are kept pending.
+    fn process_pending_blocks(
+        &mut self,
+        cancelled_block_hash: &[u8; 32],
+    ) -> Result<Option<ChallengeContext>> {
+        let mut pending_blocks = std::mem::take(&mut self.pending_blocks).into_iter();
+        while let Some(pending_block) = pending_blocks.next() {
+            let PendingBlock {
+                l2block,
+                header_info,
+                deposition_requests,
+            } = pending_block.clone();
+            if &l2block.hash() == cancelled_block_hash {
+                self.apply_accepted_block(l2block, header_info, deposition_requests)?;
+            } else if let Some(challenge_context) =
+                self.process_block(l2block, header_info, deposition_requests)?
+            {
+                self.bad_block_context = Some(challenge_context.args.clone());
+                self.pending_blocks.push(pending_block);
+                self.pending_blocks.extend(pending_blocks);
+                return Ok(Some(challenge_context));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Apply a block which is accepted by layer1 without checking bad txs
+    fn apply_accepted_block(
+        &mut self,
+        l2block: L2Block,
+        header_info: HeaderInfo,
+        deposition_requests: Vec<DepositionRequest>,
+    ) -> Result<()> {
+        let tip_number: u64 = self.local_state.tip.raw().number().unpack();
+        let number: u64 = l2block.raw().number().unpack();
+        if number != tip_number + 1 {
+            return Err(ChainError::InvalidBlockNumber {
+                expected: tip_number + 1,
+                actual: number,
+            }
+            .into());
+        }
+        let args = StateTransitionArgs {
+            l2block: l2block.clone(),
+            deposition_requests: deposition_requests.clone(),
+        };
+        self.store.begin_state_journal();
+        let result = match self
+            .generator
+            .apply_accepted_state_transition(&mut self.store, args)
+        {
+            Ok(result) => result,
+            Err(err) => {
+                self.store.rollback_state_journal()?;
+                return Err(anyhow!(
+                    "block {} is accepted by layer1 but failed to apply locally, error: {}",
+                    number,
+                    err
+                ));
+            }
+        };
+        self.store.insert_block(
+            l2block.clone(),
+            header_info,
+            deposition_requests,
+            result.receipts,
         )?;
-        Ok(SyncEvent::Success)
+        self.store.attach_block(l2block.clone())?;
+        self.local_state.tip = l2block.clone();
+        self.event_notifier
+            .notify(ChainEvent::NewTip { block: l2block });
+        Ok(())
+    }
+
+    /// Apply the block to the state until the tx_index, the tx itself is not applied
+    fn apply_block_until_tx<S: State + CodeStore>(
+        &self,
+        state: &mut S,
+        l2block: &L2Block,
+        deposition_requests: &[DepositionRequest],
+        tx_index: u32,
+    ) -> Result<()> {
+        let withdrawal_requests: Vec<_> = l2block.withdrawal_requests().into_iter().collect();
+        state.apply_withdrawal_requests(&withdrawal_requests)?;
+        state.apply_deposition_requests(deposition_requests)?;
+        let block_info = get_block_info(&l2block.raw());
+        for tx in l2block.transactions().into_iter().take(tx_index as usize) {
+            let run_result = self.generator.execute(&*state, &block_info, &tx.raw())?;
+            state.apply_run_result(&run_result)?;
+        }
+        Ok(())
+    }
+
+    /// Re-execute the challenged transaction
+    ///
+    /// Returns the cancel challenge witness and the tx receipt if the tx is valid,
+    /// returns None if the tx is failed to execute which means the challenge is valid.
+    fn build_cancel_challenge(
+        &self,
+        context: &StartChallenge,
+    ) -> Result<Option<(CancelChallenge, TxReceipt)>> {
+        let block_hash: [u8; 32] = context.block_hash().unpack();
+        let tx_index: u32 = context.tx_index().unpack();
+        let block = self
+            .store
+            .get_block(&block_hash.into())?
+            .ok_or_else(|| anyhow!("can't find the challenged block"))?;
+        let tx = block
+            .transactions()
+            .get(tx_index as usize)
+            .ok_or_else(|| anyhow!("can't find the challenged tx, tx_index: {}", tx_index))?;
+        let deposition_requests = self
+            .store
+            .get_block_deposition_requests(&block_hash.into())?
+            .ok_or_else(|| anyhow!("can't find deposition requests of the challenged block"))?;
+
+        // replay the block to the state before the challenged tx
+        let mut state = self.store.new_overlay_before_block(&block_hash.into())?;
+        self.apply_block_until_tx(&mut state, &block, &deposition_requests, tx_index)?;
+        let raw_block = block.raw();
+        let block_info = get_block_info(&raw_block);
+
+        // execute the challenged tx
+        let raw_tx = tx.raw();
+        let sender_id: u32 = raw_tx.from_id().unpack();
+        let receiver_id: u32 = raw_tx.to_id().unpack();
+        let nonce: u32 = raw_tx.nonce().unpack();
+        if nonce != state.get_nonce(sender_id)? {
+            return Ok(None);
+        }
+        let account_count = state.get_account_count()?;
+        let run_result = match self.generator.execute(&state, &block_info, &raw_tx) {
+            Ok(run_result) => run_result,
+            Err(_) => return Ok(None),
+        };
+
+        let (kv_state, kv_state_proof) =
+            build_challenge_kv_state(&state, &run_result, sender_id, receiver_id)?;
+        let tx_proof = calculate_merkle_proof(
+            block
+                .transactions()
+                .into_iter()
+                .map(|tx| tx.witness_hash())
+                .collect(),
+            tx_index,
+        )
+        .map_err(|err| anyhow!("merkle proof error: {:?}", err))?;
+        let scripts = [sender_id, receiver_id]
+            .iter()
+            .map(|id| {
+                let script_hash = state.get_script_hash(*id)?;
+                state
+                    .get_script(&script_hash)
+                    .ok_or_else(|| anyhow!("can't find script of account {}", id))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let return_data_hash = {
+            let mut hasher = new_blake2b();
+            hasher.update(&run_result.return_data);
+            let mut buf = [0u8; 32];
+            hasher.finalize(&mut buf);
+            buf
+        };
+
+        // generate the receipt
+        state.apply_run_result(&run_result)?;
+        let compacted_post_account_root = state.calculate_compacted_account_root()?;
+        let tx_receipt = TxReceipt {
+            tx_witness_hash: tx.witness_hash().into(),
+            compacted_post_account_root,
+            read_data_hashes: run_result.read_data.keys().cloned().collect(),
+            used_cycles: run_result.used_cycles,
+        };
+
+        let kv_state: Vec<([u8; 32], [u8; 32])> = kv_state
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        let witness = CancelChallenge::new_builder()
+            .raw_l2block(raw_block)
+            .l2tx(tx)
+            .tx_proof(tx_proof.0.pack())
+            .kv_state(kv_state.pack())
+            .kv_state_proof(kv_state_proof.pack())
+            .scripts(scripts.pack())
+            .return_data_hash(return_data_hash.pack())
+            .account_count(account_count.pack())
+            .build();
+        Ok(Some((witness, tx_receipt)))
     }
 
     fn process_block(
@@ -318,24 +989,57 @@ impl Chain {
         deposition_requests: Vec<DepositionRequest>,
     ) -> Result<Option<ChallengeContext>> {
         let tip_number: u64 = self.local_state.tip.raw().number().unpack();
-        assert!(
-            l2block.raw().number().unpack() == tip_number + 1,
-            "new l2block number must be the successor of the tip"
-        );
+        let number: u64 = l2block.raw().number().unpack();
+        if number != tip_number + 1 {
+            return Err(ChainError::InvalidBlockNumber {
+                expected: tip_number + 1,
+                actual: number,
+            }
+            .into());
+        }
 
         // process l2block
         let args = StateTransitionArgs {
             l2block: l2block.clone(),
-            deposition_requests,
+            deposition_requests: deposition_requests.clone(),
         };
         // process transactions
+        // record the state changes, so the block can be detached when layer1 reorg
+        self.store.begin_state_journal();
         let result = match self.generator.apply_state_transition(&mut self.store, args) {
             Ok(result) => result,
-            Err(err) => {
-                // handle tx error
-                match err {
-                    GeneratorError::Transaction(err) => {
-                        // TODO run offchain validator before send challenge, to make sure the block is bad
+            Err(GeneratorError::Transaction(parallel_err)) => {
+                // revert the partially applied block
+                self.store.rollback_state_journal()?;
+                // replay the block sequentially before send challenge, to make sure the block is bad,
+                // nonces and withdrawal requests are re-checked by the replay as well
+                let args = StateTransitionArgs {
+                    l2block: l2block.clone(),
+                    deposition_requests: deposition_requests.clone(),
+                };
+                self.store.begin_state_journal();
+                match self
+                    .generator
+                    .apply_sequential_state_transition(&mut self.store, args)
+                {
+                    Ok(result) => {
+                        // the sequential execution is the reference, accept the block
+                        let number: u64 = l2block.raw().number().unpack();
+                        eprintln!(
+                            "BUG: block {} failed to apply in parallel but succeeded on the sequential replay, parallel error: {}",
+                            number, parallel_err
+                        );
+                        result
+                    }
+                    Err(err) => {
+                        self.store.rollback_state_journal()?;
+                        let err = match err {
+                            GeneratorError::Transaction(err) => err,
+                            err => return Err(err.into()),
+                        };
+                        // NOTICE the backend validator is not run before the challenge,
+                        // it verifies the tx with the challenge witness loaded by CKB syscalls
+                        // which are not emulated off-chain, the failure is confirmed by the replay only.
                         let block_hash: [u8; 32] = err.context.block_hash().unpack();
                         let block_proof = self
                             .store()
@@ -352,20 +1056,30 @@ impl Chain {
                         };
                         return Ok(Some(challenge_context));
                     }
-                    err => return Err(err.into()),
                 }
             }
+            Err(err) => {
+                // revert the partially applied block
+                self.store.rollback_state_journal()?;
+                return Err(err.into());
+            }
         };
 
         // update chain
-        self.store
-            .insert_block(l2block.clone(), header_info.clone(), result.receipts)?;
+        self.store.insert_block(
+            l2block.clone(),
+            header_info.clone(),
+            deposition_requests,
+            result.receipts,
+        )?;
         self.store.attach_block(l2block.clone())?;
-        self.local_state.tip = l2block;
+        self.local_state.tip = l2block.clone();
+        self.event_notifier
+            .notify(ChainEvent::NewTip { block: l2block });
         Ok(None)
     }
 
-    /// Produce an unsigned new block
+    /// Produce a new block signed by the aggregator's signer
     ///
     /// This function should be called in the turn that the current aggregator to produce the next block,
     /// otherwise the produced block may invalided by the state-validator contract.
@@ -374,8 +1088,16 @@ impl Chain {
             aggregator_id,
             deposition_requests,
         } = param;
+        let signer = self
+            .signer
+            .as_ref()
+            .ok_or_else(|| anyhow!("signer is required to produce blocks"))?;
 
         let tx_pool_pkg = self.tx_pool.lock().package(&deposition_requests)?;
+        self.event_notifier.notify(ChainEvent::TxPoolPackaged {
+            tx_count: tx_pool_pkg.tx_receipts.len(),
+            withdrawal_count: tx_pool_pkg.withdrawal_requests.len(),
+        });
         // take txs from tx pool
         // produce block
         let parent_number: u64 = self.local_state.tip.raw().number().unpack();
@@ -429,6 +1151,7 @@ impl Chain {
             .withdrawal_requests_root(withdrawal_requests_root.pack())
             .submit_transactions(submit_txs)
             .build();
+        let signature = signer.sign(&raw_block.hash())?;
         // generate block fields from current state
         let kv_state: Vec<(H256, H256)> = tx_pool_pkg
             .touched_keys
@@ -478,6 +1201,7 @@ impl Chain {
             .transactions(txs.pack())
             .withdrawal_requests(tx_pool_pkg.withdrawal_requests.pack())
             .block_proof(block_proof.0.pack())
+            .signature(signature.pack())
             .build();
         let post_block = {
             let post_block_root: [u8; 32] = block_proof
@@ -489,11 +1213,12 @@ impl Chain {
                 .count(block_count.pack())
                 .build()
         };
-        // reverted_block_root: Byte32,
+        let reverted_block_root: [u8; 32] = (*self.store.reverted_block_smt().root()).into();
         let last_finalized_block_number = number.saturating_sub(FINALIZE_BLOCKS);
         let global_state = GlobalState::new_builder()
             .account(post_account)
             .block(post_block)
+            .reverted_block_root(reverted_block_root.pack())
             .last_finalized_block_number(last_finalized_block_number.pack())
             .status((Status::Running as u8).into())
             .build();
@@ -504,6 +1229,51 @@ impl Chain {
     }
 }
 
+/// Collect the kv state of the challenged tx and its merkle proof
+///
+/// Besides the keys touched by the tx, the challenge-lock reads the script hashes
+/// of the sender and the receiver from the kv state.
+pub(crate) fn build_challenge_kv_state<S: SMTStore<H256>>(
+    state: &OverlayStore<S>,
+    run_result: &RunResult,
+    sender_id: u32,
+    receiver_id: u32,
+) -> Result<(Vec<(H256, H256)>, Vec<u8>)> {
+    let touched_keys: BTreeSet<H256> = run_result
+        .read_values
+        .keys()
+        .chain(run_result.write_values.keys())
+        .cloned()
+        .chain(
+            [sender_id, receiver_id]
+                .iter()
+                .map(|id| build_account_field_key(*id, GW_ACCOUNT_SCRIPT_HASH)),
+        )
+        .collect();
+    let kv_state = touched_keys
+        .into_iter()
+        .map(|key| state.get_raw(&key).map(|value| (key, value)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let kv_state_proof = state
+        .account_smt()
+        .merkle_proof(kv_state.iter().map(|(key, _)| *key).collect())?
+        .compile(kv_state.clone())?
+        .0;
+    Ok((kv_state, kv_state_proof))
+}
+
+fn check_status_transition(action: &'static str, expected: Status, actual: Status) -> Result<()> {
+    if actual != expected {
+        return Err(ChainError::InvalidStatusTransition {
+            action,
+            expected,
+            actual,
+        }
+        .into());
+    }
+    Ok(())
+}
+
 fn unixtime() -> Result<u64> {
     SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
@@ -1,8 +1,6 @@
-use anyhow::{anyhow, Error, Result};
-use gw_common::blake2b::new_blake2b;
+use anyhow::Result;
 use lazy_static::lazy_static;
-use secp256k1::recovery::{RecoverableSignature, RecoveryId};
-use std::convert::{TryFrom, TryInto};
+use secp256k1::SecretKey;
 
 lazy_static! {
     pub static ref SECP256K1: secp256k1::Secp256k1<secp256k1::All> = secp256k1::Secp256k1::new();
@@ -12,36 +10,12 @@ pub type Message = [u8; 32];
 pub type PubkeyHash = [u8; 20];
 pub struct Signature(pub [u8; 65]);
 
-impl TryFrom<&Signature> for RecoverableSignature {
-    type Error = Error;
-
-    fn try_from(sig: &Signature) -> Result<Self> {
-        let recid = RecoveryId::from_i32(sig.0[64] as i32)?;
-        let data = &sig.0[..64];
-        let recoverable_sig = RecoverableSignature::from_compact(data, recid)?;
-        Ok(recoverable_sig)
-    }
-}
-
-pub fn verify_signature(
-    sig: &Signature,
-    msg: &Message,
-    expected_pubkey_hash: &PubkeyHash,
-) -> Result<()> {
+pub fn sign_message(secret_key: &SecretKey, msg: &Message) -> Result<Signature> {
     let msg = secp256k1::Message::from_slice(msg)?;
-    let sig: RecoverableSignature = sig.try_into()?;
-    let pubkey = SECP256K1.recover(&msg, &sig)?;
-    let pubkey_hash = {
-        let mut buf = [0u8; 32];
-        let mut hasher = new_blake2b();
-        hasher.update(&pubkey.serialize());
-        hasher.finalize(&mut buf);
-        let mut pubkey_hash = [0u8; 20];
-        pubkey_hash.copy_from_slice(&buf[..20]);
-        pubkey_hash
-    };
-    if &pubkey_hash != expected_pubkey_hash {
-        return Err(anyhow!("wrong signature"));
-    }
-    Ok(())
+    let sig = SECP256K1.sign_recoverable(&msg, secret_key);
+    let (recid, data) = sig.serialize_compact();
+    let mut buf = [0u8; 65];
+    buf[..64].copy_from_slice(&data);
+    buf[64] = recid.to_i32() as u8;
+    Ok(Signature(buf))
 }
@@ -0,0 +1,230 @@
+//! Custodian cells of the rollup
+//!
+//! Deposited assets are locked in the custodian cells on layer1, withdrawals are paid from them.
+//! The tx pool queries the liquidity from a `CustodianProvider` and only packages withdrawal requests
+//! which can be settled by the custodian cells.
+
+use crate::error::CustodianError;
+use anyhow::Result;
+use gw_common::CKB_SUDT_SCRIPT_HASH;
+use gw_types::{packed::WithdrawalRequest, prelude::*};
+use std::collections::HashMap;
+
+/// Provide the liquidity of the custodian cells, usually by indexing the layer1 chain
+pub trait CustodianProvider: Send + Sync {
+    fn query_liquidity(&self) -> Result<CustodianLiquidity>;
+}
+
+/// Assets of the custodian cells which can be used to pay withdrawals
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CustodianLiquidity {
+    /// total capacity of the custodian cells, in shannons
+    pub capacity: u64,
+    /// sUDT amount of the custodian cells, indexed by the sUDT script hash
+    pub sudt_amounts: HashMap<[u8; 32], u128>,
+}
+
+impl CustodianLiquidity {
+    /// Reserve assets to pay the withdrawal request
+    ///
+    /// The remain capacity must be enough to create change cells:
+    /// a sUDT change cell for each sUDT which has remain amount, or a CKB change cell if there is no sUDT change.
+    /// The liquidity is unchanged if an error is returned.
+    pub fn reserve(
+        &mut self,
+        withdrawal_request: &WithdrawalRequest,
+        min_change_capacity: u64,
+    ) -> Result<(), CustodianError> {
+        let raw = withdrawal_request.raw();
+        let capacity: u64 = raw.capacity().unpack();
+        let sudt_script_hash: [u8; 32] = raw.sudt_script_hash().unpack();
+        let amount: u128 = raw.amount().unpack();
+
+        let mut sudt_amounts = self.sudt_amounts.clone();
+        if sudt_script_hash != CKB_SUDT_SCRIPT_HASH && amount > 0 {
+            let sudt_amount = sudt_amounts.entry(sudt_script_hash).or_insert(0);
+            if *sudt_amount < amount {
+                return Err(CustodianError::InsufficientSUDT {
+                    sudt_script_hash: hex::encode(&sudt_script_hash),
+                    expected: amount,
+                    actual: *sudt_amount,
+                });
+            }
+            *sudt_amount -= amount;
+        }
+        let remain_capacity =
+            self.capacity
+                .checked_sub(capacity)
+                .ok_or(CustodianError::InsufficientCapacity {
+                    expected: capacity,
+                    actual: self.capacity,
+                })?;
+        // capacity of the change cells
+        let sudt_change_cells = sudt_amounts.values().filter(|amount| **amount > 0).count() as u64;
+        let change_cells = if sudt_change_cells == 0 && remain_capacity > 0 {
+            1
+        } else {
+            sudt_change_cells
+        };
+        let change_capacity = min_change_capacity.saturating_mul(change_cells);
+        if remain_capacity < change_capacity {
+            return Err(CustodianError::InsufficientCapacity {
+                expected: capacity.saturating_add(change_capacity),
+                actual: self.capacity,
+            });
+        }
+        self.capacity = remain_capacity;
+        self.sudt_amounts = sudt_amounts;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gw_types::packed::RawWithdrawalRequest;
+
+    const SUDT_A: [u8; 32] = [1u8; 32];
+    const SUDT_B: [u8; 32] = [2u8; 32];
+    const MIN_CHANGE_CAPACITY: u64 = 100;
+
+    fn withdrawal_request(
+        capacity: u64,
+        sudt_script_hash: [u8; 32],
+        amount: u128,
+    ) -> WithdrawalRequest {
+        let raw = RawWithdrawalRequest::new_builder()
+            .capacity(capacity.pack())
+            .sudt_script_hash(sudt_script_hash.pack())
+            .amount(amount.pack())
+            .build();
+        WithdrawalRequest::new_builder().raw(raw).build()
+    }
+
+    fn liquidity(capacity: u64, sudt_amounts: &[([u8; 32], u128)]) -> CustodianLiquidity {
+        CustodianLiquidity {
+            capacity,
+            sudt_amounts: sudt_amounts.iter().cloned().collect(),
+        }
+    }
+
+    #[test]
+    fn test_reserve_ckb_with_change() {
+        let mut custodian = liquidity(1000, &[]);
+        custodian
+            .reserve(
+                &withdrawal_request(400, CKB_SUDT_SCRIPT_HASH, 0),
+                MIN_CHANGE_CAPACITY,
+            )
+            .expect("reserve");
+        assert_eq!(custodian, liquidity(600, &[]));
+        // the CKB change cell must keep the min capacity
+        let err = custodian
+            .reserve(
+                &withdrawal_request(550, CKB_SUDT_SCRIPT_HASH, 0),
+                MIN_CHANGE_CAPACITY,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            CustodianError::InsufficientCapacity {
+                expected: 650,
+                actual: 600
+            }
+        );
+        assert_eq!(custodian, liquidity(600, &[]));
+    }
+
+    #[test]
+    fn test_reserve_all_capacity_without_change() {
+        let mut custodian = liquidity(1000, &[]);
+        custodian
+            .reserve(
+                &withdrawal_request(1000, CKB_SUDT_SCRIPT_HASH, 0),
+                MIN_CHANGE_CAPACITY,
+            )
+            .expect("reserve");
+        assert_eq!(custodian, liquidity(0, &[]));
+        let err = custodian
+            .reserve(
+                &withdrawal_request(1, CKB_SUDT_SCRIPT_HASH, 0),
+                MIN_CHANGE_CAPACITY,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            CustodianError::InsufficientCapacity {
+                expected: 1,
+                actual: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_reserve_sudt_change_cells() {
+        // each sUDT which has remain amount needs a change cell
+        let mut custodian = liquidity(1000, &[(SUDT_A, 50), (SUDT_B, 10)]);
+        let err = custodian
+            .reserve(&withdrawal_request(850, SUDT_A, 20), MIN_CHANGE_CAPACITY)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            CustodianError::InsufficientCapacity {
+                expected: 1050,
+                actual: 1000
+            }
+        );
+        assert_eq!(custodian, liquidity(1000, &[(SUDT_A, 50), (SUDT_B, 10)]));
+
+        custodian
+            .reserve(&withdrawal_request(800, SUDT_A, 20), MIN_CHANGE_CAPACITY)
+            .expect("reserve");
+        assert_eq!(custodian, liquidity(200, &[(SUDT_A, 30), (SUDT_B, 10)]));
+
+        // the sUDT A change cell is not needed once it is withdrawn entirely
+        custodian
+            .reserve(&withdrawal_request(100, SUDT_A, 30), MIN_CHANGE_CAPACITY)
+            .expect("reserve");
+        assert_eq!(custodian, liquidity(100, &[(SUDT_A, 0), (SUDT_B, 10)]));
+    }
+
+    #[test]
+    fn test_reserve_insufficient_sudt() {
+        let mut custodian = liquidity(1000, &[(SUDT_A, 50)]);
+        let err = custodian
+            .reserve(&withdrawal_request(100, SUDT_A, 51), MIN_CHANGE_CAPACITY)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            CustodianError::InsufficientSUDT {
+                sudt_script_hash: hex::encode(&SUDT_A),
+                expected: 51,
+                actual: 50
+            }
+        );
+        let err = custodian
+            .reserve(&withdrawal_request(100, SUDT_B, 1), MIN_CHANGE_CAPACITY)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            CustodianError::InsufficientSUDT {
+                sudt_script_hash: hex::encode(&SUDT_B),
+                expected: 1,
+                actual: 0
+            }
+        );
+        assert_eq!(custodian, liquidity(1000, &[(SUDT_A, 50)]));
+    }
+
+    #[test]
+    fn test_reserve_ckb_ignores_sudt_amount() {
+        let mut custodian = liquidity(1000, &[]);
+        custodian
+            .reserve(
+                &withdrawal_request(400, CKB_SUDT_SCRIPT_HASH, 100),
+                MIN_CHANGE_CAPACITY,
+            )
+            .expect("reserve");
+        assert_eq!(custodian, liquidity(600, &[]));
+    }
+}
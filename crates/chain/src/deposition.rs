@@ -0,0 +1,288 @@
+//! Collect deposition requests from the layer1 transaction
+//!
+//! The rules are the same as the `join` action of the state-validator:
+//! input cells locked by the deposition lock of this rollup are converted to deposition requests.
+//! The deposition lock and the Simple UDT type are configured in the `ChainConfig`.
+
+use anyhow::{anyhow, Result};
+use ckb_types::{
+    bytes::Bytes,
+    packed::{CellOutput, Script as CKBScript, Transaction},
+    prelude::{Entity, Unpack},
+};
+use gw_types::{
+    core::ScriptHashType as GWScriptHashType,
+    packed::{DepositionLockArgs, DepositionRequest, Script},
+    prelude::{Builder as GWBuilder, Entity as GWEntity, Pack as GWPack},
+};
+
+/// A resolved input cell of the layer1 transaction
+#[derive(Debug, Clone)]
+pub struct InputCell {
+    pub output: CellOutput,
+    pub data: Bytes,
+}
+
+/// Collect deposition requests from the input cells
+///
+/// `input_cells` must be the resolved cells of the transaction inputs, in the same order.
+/// Cells are matched with the `code_hash` and `hash_type` of `deposition_lock` and `l1_sudt_type`.
+pub fn collect_deposition_requests(
+    tx: &Transaction,
+    input_cells: &[InputCell],
+    rollup_type_script_hash: &[u8; 32],
+    deposition_lock: &CKBScript,
+    l1_sudt_type: &CKBScript,
+) -> Result<Vec<DepositionRequest>> {
+    let inputs_len = tx.raw().inputs().len();
+    if inputs_len != input_cells.len() {
+        return Err(anyhow!(
+            "input cells mismatch, expected {} actual {}",
+            inputs_len,
+            input_cells.len()
+        ));
+    }
+    let mut deposition_requests = Vec::new();
+    for cell in input_cells {
+        if let Some(request) =
+            parse_deposition_request(cell, rollup_type_script_hash, deposition_lock, l1_sudt_type)?
+        {
+            deposition_requests.push(request);
+        }
+    }
+    Ok(deposition_requests)
+}
+
+fn parse_deposition_request(
+    cell: &InputCell,
+    rollup_type_script_hash: &[u8; 32],
+    deposition_lock: &CKBScript,
+    l1_sudt_type: &CKBScript,
+) -> Result<Option<DepositionRequest>> {
+    let lock = cell.output.lock();
+    if !is_same_code(&lock, deposition_lock) {
+        return Ok(None);
+    }
+    // args: rollup_type_hash | deposition lock args
+    let args: Bytes = lock.args().unpack();
+    // ignore deposition request that do not belong to Rollup
+    if args.len() < 32 || &args[..32] != rollup_type_script_hash {
+        return Ok(None);
+    }
+    let lock_args = DepositionLockArgs::from_slice(&args[32..])
+        .map_err(|err| anyhow!("invalid deposition lock args: {}", err))?;
+
+    // CKB is deposited as the builtin CKB Simple UDT
+    let (sudt_script, amount) = match cell.output.type_().to_opt() {
+        Some(type_) => {
+            if !is_same_code(&type_, l1_sudt_type) {
+                return Err(anyhow!("deposition cell's type is not Simple UDT"));
+            }
+            if cell.data.len() < 16 {
+                return Err(anyhow!("invalid Simple UDT data"));
+            }
+            let mut buf = [0u8; 16];
+            buf.copy_from_slice(&cell.data[..16]);
+            let sudt_script = Script::from_slice(type_.as_slice())
+                .map_err(|err| anyhow!("invalid Simple UDT script: {}", err))?;
+            (sudt_script, u128::from_le_bytes(buf))
+        }
+        None => (ckb_sudt_script(), 0),
+    };
+    let capacity: u64 = cell.output.capacity().unpack();
+    let request = DepositionRequest::new_builder()
+        .capacity(capacity.pack())
+        .amount(amount.pack())
+        .sudt_script(sudt_script)
+        .script(lock_args.layer2_lock())
+        .build();
+    Ok(Some(request))
+}
+
+/// Whether the script runs the same code as the expected script, args are ignored
+fn is_same_code(script: &CKBScript, expected: &CKBScript) -> bool {
+    script.code_hash().as_slice() == expected.code_hash().as_slice()
+        && script.hash_type() == expected.hash_type()
+}
+
+/// The script of the builtin CKB Simple UDT, it's hash is `CKB_SUDT_SCRIPT_HASH`
+fn ckb_sudt_script() -> Script {
+    Script::new_builder()
+        .code_hash([0u8; 32].pack())
+        .hash_type(GWScriptHashType::Data.into())
+        .args(vec![0u8; 32].pack())
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_deposition_requests, InputCell};
+    use anyhow::Result;
+    use ckb_types::{
+        bytes::Bytes,
+        core::ScriptHashType,
+        packed::{CellInput, CellOutput, RawTransaction, Script as CKBScript, Transaction},
+        prelude::{Builder, Entity, Pack},
+    };
+    use gw_common::CKB_SUDT_SCRIPT_HASH;
+    use gw_types::{
+        packed::DepositionRequest,
+        prelude::{Entity as GWEntity, Unpack as GWUnpack},
+    };
+
+    /// layer2 types are built in a separate module, the `Pack` traits of ckb-types and gw-types
+    /// are both implemented for the primitive types
+    mod layer2 {
+        use gw_types::{
+            core::ScriptHashType,
+            packed::{DepositionLockArgs, Script},
+            prelude::*,
+        };
+
+        pub fn layer2_lock() -> Script {
+            Script::new_builder()
+                .code_hash([4u8; 32].pack())
+                .hash_type(ScriptHashType::Type.into())
+                .args(vec![5u8; 20].pack())
+                .build()
+        }
+
+        pub fn lock_args(rollup_type_hash: [u8; 32]) -> Vec<u8> {
+            let lock_args = DepositionLockArgs::new_builder()
+                .owner_lock_hash([6u8; 32].pack())
+                .layer2_lock(layer2_lock())
+                .cancel_timeout(100u64.pack())
+                .build();
+            let mut args = rollup_type_hash.to_vec();
+            args.extend_from_slice(lock_args.as_slice());
+            args
+        }
+    }
+    use layer2::{layer2_lock, lock_args};
+
+    const ROLLUP_TYPE_HASH: [u8; 32] = [1u8; 32];
+
+    fn deposition_lock() -> CKBScript {
+        CKBScript::new_builder()
+            .code_hash([2u8; 32].pack())
+            .hash_type(ScriptHashType::Type.into())
+            .build()
+    }
+
+    fn l1_sudt_type() -> CKBScript {
+        CKBScript::new_builder()
+            .code_hash([3u8; 32].pack())
+            .hash_type(ScriptHashType::Type.into())
+            .build()
+    }
+
+    fn deposition_cell(args: Vec<u8>, type_: Option<CKBScript>, data: Vec<u8>) -> InputCell {
+        let lock = deposition_lock()
+            .as_builder()
+            .args(Bytes::from(args).pack())
+            .build();
+        let output = CellOutput::new_builder()
+            .capacity(500_0000_0000u64.pack())
+            .lock(lock)
+            .type_(type_.pack())
+            .build();
+        InputCell {
+            output,
+            data: Bytes::from(data),
+        }
+    }
+
+    fn collect(input_cells: &[InputCell]) -> Result<Vec<DepositionRequest>> {
+        let inputs: Vec<_> = input_cells
+            .iter()
+            .map(|_| CellInput::new_builder().build())
+            .collect();
+        let tx = Transaction::new_builder()
+            .raw(RawTransaction::new_builder().inputs(inputs.pack()).build())
+            .build();
+        collect_deposition_requests(
+            &tx,
+            input_cells,
+            &ROLLUP_TYPE_HASH,
+            &deposition_lock(),
+            &l1_sudt_type(),
+        )
+    }
+
+    #[test]
+    fn test_ckb_deposition() {
+        let cell = deposition_cell(lock_args(ROLLUP_TYPE_HASH), None, Vec::new());
+        let requests = collect(&[cell]).expect("collect");
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        let capacity: u64 = request.capacity().unpack();
+        let amount: u128 = request.amount().unpack();
+        assert_eq!(capacity, 500_0000_0000);
+        assert_eq!(amount, 0);
+        assert_eq!(request.sudt_script().hash(), CKB_SUDT_SCRIPT_HASH);
+        assert_eq!(request.script().as_slice(), layer2_lock().as_slice());
+    }
+
+    #[test]
+    fn test_sudt_deposition() {
+        let sudt_type = l1_sudt_type()
+            .as_builder()
+            .args(Bytes::from(vec![7u8; 32]).pack())
+            .build();
+        let mut data = 1000u128.to_le_bytes().to_vec();
+        // extra data of the Simple UDT cell is ignored
+        data.extend_from_slice(&[8u8; 4]);
+        let cell = deposition_cell(lock_args(ROLLUP_TYPE_HASH), Some(sudt_type.clone()), data);
+        let requests = collect(&[cell]).expect("collect");
+        assert_eq!(requests.len(), 1);
+        let amount: u128 = requests[0].amount().unpack();
+        assert_eq!(amount, 1000);
+        assert_eq!(requests[0].sudt_script().as_slice(), sudt_type.as_slice());
+    }
+
+    #[test]
+    fn test_ignore_other_cells() {
+        // deposition of another rollup
+        let foreign_cell = deposition_cell(lock_args([9u8; 32]), None, Vec::new());
+        // same code hash with a different hash type
+        let mut data_lock_cell = deposition_cell(lock_args(ROLLUP_TYPE_HASH), None, Vec::new());
+        let lock = data_lock_cell
+            .output
+            .lock()
+            .as_builder()
+            .hash_type(ScriptHashType::Data.into())
+            .build();
+        data_lock_cell.output = data_lock_cell.output.as_builder().lock(lock).build();
+        let requests = collect(&[foreign_cell, data_lock_cell]).expect("collect");
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_deposition() {
+        // invalid deposition lock args
+        let mut args = ROLLUP_TYPE_HASH.to_vec();
+        args.extend_from_slice(&[0u8; 8]);
+        let cell = deposition_cell(args, None, Vec::new());
+        assert!(collect(&[cell]).is_err());
+
+        // the type is not the Simple UDT
+        let other_type = CKBScript::new_builder()
+            .code_hash([10u8; 32].pack())
+            .hash_type(ScriptHashType::Type.into())
+            .build();
+        let cell = deposition_cell(
+            lock_args(ROLLUP_TYPE_HASH),
+            Some(other_type),
+            1000u128.to_le_bytes().to_vec(),
+        );
+        assert!(collect(&[cell]).is_err());
+
+        // the Simple UDT data is too short
+        let cell = deposition_cell(
+            lock_args(ROLLUP_TYPE_HASH),
+            Some(l1_sudt_type()),
+            vec![0u8; 8],
+        );
+        assert!(collect(&[cell]).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use gw_types::core::Status;
+use thiserror::Error;
+
+/// Errors caused by unexpected layer1 actions
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum ChainError {
+    #[error("invalid rollup status {0}")]
+    InvalidStatus(u8),
+    #[error("header number {number} is less than the last synced number {last_synced}")]
+    OutOfOrderHeader { number: u64, last_synced: u64 },
+    #[error("block number {actual} is not the successor of the tip, expected {expected}")]
+    InvalidBlockNumber { expected: u64, actual: u64 },
+    #[error("invalid status transition of {action}, expected {expected:?} actual {actual:?}")]
+    InvalidStatusTransition {
+        action: &'static str,
+        expected: Status,
+        actual: Status,
+    },
+    #[error("unsupported syncing state: status {status:?} action {action}")]
+    UnsupportedSyncState {
+        status: Status,
+        action: &'static str,
+    },
+}
+
+/// Errors of withdrawals which can't be paid by the custodian cells
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum CustodianError {
+    #[error("insufficient custodian capacity, expected {expected} actual {actual}")]
+    InsufficientCapacity { expected: u64, actual: u64 },
+    #[error("insufficient custodian sUDT {sudt_script_hash}, expected {expected} actual {actual}")]
+    InsufficientSUDT {
+        sudt_script_hash: String,
+        expected: u128,
+        actual: u128,
+    },
+}
@@ -0,0 +1,63 @@
+//! Chain events
+//!
+//! Subscribers receive events through a channel instead of polling the chain state.
+
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use gw_common::H256;
+use gw_types::{
+    core::Status,
+    packed::{CancelChallenge, L2Block, StartChallenge},
+};
+use parking_lot::Mutex;
+
+/// Max number of events buffered for a subscriber,
+/// new events are dropped if the subscriber doesn't consume them in time.
+const SUBSCRIBER_CHANNEL_SIZE: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    /// a new block is attached to the tip
+    NewTip { block: L2Block },
+    /// the tip block is detached by a layer1 reorg
+    BlockDetached { block: L2Block },
+    /// blocks are reverted by a successful challenge
+    BlockReverted { block_hashes: Vec<H256> },
+    /// a challenge is started on layer1
+    ChallengeStarted { context: StartChallenge },
+    /// a challenge is cancelled on layer1
+    ChallengeCancelled { context: CancelChallenge },
+    /// rollup status is changed
+    StatusChanged { status: Status },
+    /// txs and withdrawals are packaged from the tx pool to produce a block
+    TxPoolPackaged {
+        tx_count: usize,
+        withdrawal_count: usize,
+    },
+}
+
+#[derive(Default)]
+pub struct EventNotifier {
+    subscribers: Mutex<Vec<Sender<ChainEvent>>>,
+}
+
+impl EventNotifier {
+    pub fn subscribe(&self) -> Receiver<ChainEvent> {
+        let (sender, receiver) = bounded(SUBSCRIBER_CHANNEL_SIZE);
+        self.subscribers.lock().push(sender);
+        receiver
+    }
+
+    /// Send the event to subscribers, disconnected subscribers are removed
+    pub fn notify(&self, event: ChainEvent) {
+        self.subscribers
+            .lock()
+            .retain(|sender| match sender.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(event)) => {
+                    eprintln!("subscriber channel is full, drop event {:?}", event);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+}
@@ -8,5 +8,13 @@
 
 pub mod chain;
 mod crypto;
+pub mod custodian;
+pub mod deposition;
+pub mod error;
+pub mod event;
 pub mod next_block_context;
+pub mod signer;
+#[cfg(test)]
+mod tests;
+pub mod tx_journal;
 pub mod tx_pool;
@@ -0,0 +1,280 @@
+//! Signer of the aggregator
+//!
+//! The private key is loaded from a file keystore, two formats are supported:
+//!
+//! * raw hex: the file contains a 32 bytes hex encoded private key, with or without `0x` prefix
+//! * encrypted JSON: the web3 secret storage format used by ckb-cli and ethereum,
+//!   kdf must be `scrypt` and cipher must be `aes-128-ctr`
+
+use crate::crypto::{sign_message, Message, PubkeyHash, SECP256K1};
+use aes_ctr::{
+    cipher::{
+        generic_array::GenericArray,
+        stream::{NewStreamCipher, SyncStreamCipher},
+    },
+    Aes128Ctr,
+};
+use anyhow::{anyhow, Result};
+use gw_common::blake2b::new_blake2b;
+use gw_config::SignerConfig;
+use secp256k1::{PublicKey, SecretKey};
+use serde::Deserialize;
+use std::{fs, path::Path};
+use tiny_keccak::{Hasher, Keccak};
+
+const KEYSTORE_CIPHER: &str = "aes-128-ctr";
+const KEYSTORE_KDF: &str = "scrypt";
+
+pub struct Signer {
+    secret_key: SecretKey,
+}
+
+impl Signer {
+    pub fn from_config(config: &SignerConfig) -> Result<Self> {
+        Self::from_keystore(&config.keystore_path, config.password.as_deref())
+    }
+
+    /// Load the private key from a keystore file
+    pub fn from_keystore<P: AsRef<Path>>(path: P, password: Option<&str>) -> Result<Self> {
+        let content = fs::read_to_string(path.as_ref()).map_err(|err| {
+            anyhow!(
+                "can't read keystore {}: {}",
+                path.as_ref().to_string_lossy(),
+                err
+            )
+        })?;
+        let content = content.trim();
+        let privkey = if content.starts_with('{') {
+            let password =
+                password.ok_or_else(|| anyhow!("password is required by encrypted keystore"))?;
+            decrypt_keystore(content, password)?
+        } else {
+            decode_hex(content)?
+        };
+        Self::from_privkey(&privkey)
+    }
+
+    pub fn from_privkey(privkey: &[u8]) -> Result<Self> {
+        let secret_key = SecretKey::from_slice(privkey)
+            .map_err(|err| anyhow!("invalid private key: {}", err))?;
+        Ok(Signer { secret_key })
+    }
+
+    /// blake2b(compressed pubkey)[..20]
+    pub fn pubkey_hash(&self) -> PubkeyHash {
+        let pubkey = PublicKey::from_secret_key(&SECP256K1, &self.secret_key);
+        let mut buf = [0u8; 32];
+        let mut hasher = new_blake2b();
+        hasher.update(&pubkey.serialize());
+        hasher.finalize(&mut buf);
+        let mut pubkey_hash = [0u8; 20];
+        pubkey_hash.copy_from_slice(&buf[..20]);
+        pubkey_hash
+    }
+
+    /// Sign the message, returns the recoverable signature
+    pub fn sign(&self, message: &Message) -> Result<[u8; 65]> {
+        sign_message(&self.secret_key, message).map(|sig| sig.0)
+    }
+}
+
+#[derive(Deserialize)]
+struct Keystore {
+    #[serde(alias = "Crypto")]
+    crypto: KeystoreCrypto,
+}
+
+#[derive(Deserialize)]
+struct KeystoreCrypto {
+    cipher: String,
+    cipherparams: CipherParams,
+    ciphertext: String,
+    kdf: String,
+    kdfparams: ScryptParams,
+    mac: String,
+}
+
+#[derive(Deserialize)]
+struct CipherParams {
+    iv: String,
+}
+
+#[derive(Deserialize)]
+struct ScryptParams {
+    dklen: usize,
+    n: u64,
+    p: u32,
+    r: u32,
+    salt: String,
+}
+
+fn decrypt_keystore(content: &str, password: &str) -> Result<Vec<u8>> {
+    let keystore: Keystore =
+        serde_json::from_str(content).map_err(|err| anyhow!("invalid keystore: {}", err))?;
+    let crypto = keystore.crypto;
+    if crypto.cipher != KEYSTORE_CIPHER {
+        return Err(anyhow!("unsupported keystore cipher {}", crypto.cipher));
+    }
+    if crypto.kdf != KEYSTORE_KDF {
+        return Err(anyhow!("unsupported keystore kdf {}", crypto.kdf));
+    }
+
+    // derive key
+    let kdfparams = crypto.kdfparams;
+    if kdfparams.dklen < 32 || !kdfparams.n.is_power_of_two() {
+        return Err(anyhow!("invalid keystore kdf params"));
+    }
+    let log_n = kdfparams.n.trailing_zeros() as u8;
+    let params = scrypt::ScryptParams::new(log_n, kdfparams.r, kdfparams.p)
+        .map_err(|err| anyhow!("invalid keystore kdf params: {}", err))?;
+    let salt = decode_hex(&kdfparams.salt)?;
+    let mut derived_key = vec![0u8; kdfparams.dklen];
+    scrypt::scrypt(password.as_bytes(), &salt, &params, &mut derived_key)
+        .map_err(|err| anyhow!("derive key error: {}", err))?;
+
+    // check mac
+    let ciphertext = decode_hex(&crypto.ciphertext)?;
+    let mac = {
+        let mut buf = [0u8; 32];
+        let mut hasher = Keccak::v256();
+        hasher.update(&derived_key[16..32]);
+        hasher.update(&ciphertext);
+        hasher.finalize(&mut buf);
+        buf
+    };
+    if mac[..] != decode_hex(&crypto.mac)?[..] {
+        return Err(anyhow!("wrong keystore password"));
+    }
+
+    // decrypt, the first 32 bytes is the private key
+    let iv = decode_hex(&crypto.cipherparams.iv)?;
+    if iv.len() != 16 || ciphertext.len() < 32 {
+        return Err(anyhow!("invalid keystore cipher params"));
+    }
+    let mut plaintext = ciphertext;
+    let mut cipher = Aes128Ctr::new(
+        GenericArray::from_slice(&derived_key[..16]),
+        GenericArray::from_slice(&iv),
+    );
+    cipher.apply_keystream(&mut plaintext);
+    plaintext.truncate(32);
+    Ok(plaintext)
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    let s = s.trim();
+    let s = if s.starts_with("0x") { &s[2..] } else { s };
+    hex::decode(s).map_err(|err| anyhow!("invalid hex: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// test vector of the web3 secret storage definition, password `testpassword`
+    const WEB3_KEYSTORE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "83dbcc02d8ccb40e466191a123791e0e" },
+            "ciphertext": "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32,
+                "n": 262144,
+                "r": 1,
+                "p": 8,
+                "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+            },
+            "mac": "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+    const WEB3_PRIVKEY: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+    /// keystore with cheap kdf params, password `godwoken`
+    const KEYSTORE: &str = r#"{
+        "Crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+            "ciphertext": "78ccc85f2ef2cad8d1c3eef1944b9a5742f407d594fe437aa452fcff064f8e5f",
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32,
+                "n": 1024,
+                "r": 8,
+                "p": 1,
+                "salt": "8d7c1a5f1d8e0b9c2f4a6e3b5d7c9a1b0e2f4d6c8a0b1c3d5e7f9a2b4c6d8e0f"
+            },
+            "mac": "0a079ed3220d6e696760d540f27f5fbbfe3aacd2d9b92ce64d4de44bf04baf3d"
+        }
+    }"#;
+    const PRIVKEY: &str = "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35";
+
+    fn write_keystore(name: &str, content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("gw-signer-{}-{}", std::process::id(), name));
+        fs::write(&path, content).expect("write keystore");
+        path
+    }
+
+    #[test]
+    fn test_decrypt_web3_keystore() {
+        let privkey = decrypt_keystore(WEB3_KEYSTORE, "testpassword").expect("decrypt");
+        assert_eq!(hex::encode(privkey), WEB3_PRIVKEY);
+    }
+
+    #[test]
+    fn test_load_encrypted_keystore() {
+        let path = write_keystore("encrypted", KEYSTORE);
+        let signer = Signer::from_keystore(&path, Some("godwoken")).expect("load keystore");
+        fs::remove_file(&path).expect("remove keystore");
+        let expected = Signer::from_privkey(&decode_hex(PRIVKEY).unwrap()).unwrap();
+        assert_eq!(signer.pubkey_hash(), expected.pubkey_hash());
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let err = decrypt_keystore(KEYSTORE, "wrong password").unwrap_err();
+        assert_eq!(err.to_string(), "wrong keystore password");
+    }
+
+    #[test]
+    fn test_wrong_mac() {
+        let keystore = KEYSTORE.replace(
+            "0a079ed3220d6e696760d540f27f5fbbfe3aacd2d9b92ce64d4de44bf04baf3d",
+            "0a079ed3220d6e696760d540f27f5fbbfe3aacd2d9b92ce64d4de44bf04baf3e",
+        );
+        let err = decrypt_keystore(&keystore, "godwoken").unwrap_err();
+        assert_eq!(err.to_string(), "wrong keystore password");
+    }
+
+    #[test]
+    fn test_encrypted_keystore_requires_password() {
+        let path = write_keystore("no-password", KEYSTORE);
+        let result = Signer::from_keystore(&path, None);
+        fs::remove_file(&path).expect("remove keystore");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_load_raw_hex_keystore() {
+        let expected = Signer::from_privkey(&decode_hex(PRIVKEY).unwrap()).unwrap();
+        for (name, content) in &[
+            ("hex", PRIVKEY.to_string()),
+            ("prefixed-hex", format!("0x{}\n", PRIVKEY)),
+        ] {
+            let path = write_keystore(name, content);
+            let signer = Signer::from_keystore(&path, None).expect("load keystore");
+            fs::remove_file(&path).expect("remove keystore");
+            assert_eq!(signer.pubkey_hash(), expected.pubkey_hash());
+        }
+    }
+
+    #[test]
+    fn test_invalid_raw_hex_keystore() {
+        let path = write_keystore("invalid-hex", "0xnot a private key");
+        let result = Signer::from_keystore(&path, None);
+        fs::remove_file(&path).expect("remove keystore");
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use super::{
+    build_rollup_tx, setup_chain,
+    sync::{
+        bad_tx, build_block, global_state, header_info, submit_block, submit_block_with_deposition,
+        sync_param,
+    },
+};
+use crate::chain::{build_challenge_kv_state, Chain, L1Action, L1ActionContext, SyncEvent};
+use gw_common::{
+    h256_ext::H256Ext,
+    smt::{default_store::DefaultStore, Blake2bHasher, CompiledMerkleProof, SMT},
+    state::{build_account_field_key, build_account_key, State, GW_ACCOUNT_SCRIPT_HASH},
+    H256,
+};
+use gw_generator::{traits::StateExt, RunResult};
+use gw_store::Store;
+use gw_types::{
+    core::Status,
+    packed::{L2Block, Script, StartChallenge},
+    prelude::*,
+};
+use std::collections::BTreeMap;
+
+/// Mirror of the challenge-lock's KVState, lookups fail if the key is not in the kv state
+struct KVState {
+    kv: BTreeMap<H256, H256>,
+    proof: Vec<u8>,
+}
+
+impl KVState {
+    fn get_raw(&self, key: &H256) -> Option<H256> {
+        self.kv.get(key).cloned()
+    }
+
+    fn get_script_hash(&self, id: u32) -> Option<H256> {
+        self.get_raw(&build_account_field_key(id, GW_ACCOUNT_SCRIPT_HASH))
+    }
+
+    fn calculate_root(&self) -> H256 {
+        CompiledMerkleProof(self.proof.clone())
+            .compute_root::<Blake2bHasher>(self.kv.clone().into_iter().collect())
+            .expect("compute root")
+    }
+}
+
+fn build_script(code_hash: [u8; 32], args: &[u8]) -> Script {
+    Script::new_builder()
+        .code_hash(code_hash.pack())
+        .args(args.to_vec().pack())
+        .build()
+}
+
+#[test]
+fn test_challenge_kv_state_contains_script_hashes() {
+    let mut store: Store<DefaultStore<H256>> = Default::default();
+    let sender_script = build_script([1u8; 32], &[1u8; 20]);
+    let receiver_script = build_script([2u8; 32], &[2u8; 20]);
+    let sender_id = store
+        .create_account_from_script(sender_script.clone())
+        .expect("create sender");
+    let receiver_id = store
+        .create_account_from_script(receiver_script.clone())
+        .expect("create receiver");
+    // an unrelated account, its keys must not be required by the proof
+    store
+        .create_account_from_script(build_script([3u8; 32], &[3u8; 20]))
+        .expect("create account");
+    let counter_key = build_account_key(receiver_id, &[42u8; 32]);
+    store
+        .update_raw(counter_key, H256::from_u32(7))
+        .expect("update counter");
+    let state = store.new_overlay().expect("overlay");
+
+    // the tx reads and writes the receiver's storage only
+    let mut run_result = RunResult::default();
+    run_result
+        .read_values
+        .insert(counter_key, H256::from_u32(7));
+    run_result
+        .write_values
+        .insert(counter_key, H256::from_u32(8));
+
+    let (kv_state, kv_state_proof) =
+        build_challenge_kv_state(&state, &run_result, sender_id, receiver_id)
+            .expect("build kv state");
+    let kv_state = KVState {
+        kv: kv_state.into_iter().collect(),
+        proof: kv_state_proof,
+    };
+
+    // lookups of the challenge-lock
+    let sender_script_hash: [u8; 32] = kv_state
+        .get_script_hash(sender_id)
+        .expect("sender script hash")
+        .into();
+    assert_eq!(sender_script_hash, sender_script.hash());
+    let receiver_script_hash: [u8; 32] = kv_state
+        .get_script_hash(receiver_id)
+        .expect("receiver script hash")
+        .into();
+    assert_eq!(receiver_script_hash, receiver_script.hash());
+    assert_eq!(kv_state.get_raw(&counter_key), Some(H256::from_u32(7)));
+    // the kv state is proved against the state root before the tx
+    assert_eq!(
+        kv_state.calculate_root(),
+        state.calculate_root().expect("state root")
+    );
+}
+
+/// Sync block 1 and the bad block 2 with block 3 on top of it, then challenge block 2
+///
+/// Returns the challenge context and the blocks 2 and 3.
+pub fn sync_challenged_bad_block(chain: &mut Chain) -> (StartChallenge, L2Block, L2Block) {
+    let block1 = build_block(1, Vec::new());
+    chain
+        .sync(sync_param(vec![submit_block_with_deposition(
+            1,
+            &block1,
+            500_0000_0000,
+        )]))
+        .expect("sync block 1");
+
+    // block 2 deposits and contains a bad tx
+    let block2 = build_block(2, vec![bad_tx()]);
+    let event = chain
+        .sync(sync_param(vec![submit_block_with_deposition(
+            2,
+            &block2,
+            300_0000_0000,
+        )]))
+        .expect("sync block 2");
+    let challenge_context = match event {
+        SyncEvent::BadBlock(challenge_context) => challenge_context.args,
+        event => panic!("unexpected event {:?}", event),
+    };
+    let block3 = build_block(3, Vec::new());
+    chain
+        .sync(sync_param(vec![submit_block(3, &block3)]))
+        .expect("sync block 3");
+
+    let halting = global_state(Status::Halting);
+    let challenge = L1Action {
+        transaction: build_rollup_tx(4, halting.as_slice(), None),
+        header_info: header_info(4),
+        context: L1ActionContext::Challenge {
+            context: challenge_context.clone(),
+        },
+    };
+    let event = chain
+        .sync(sync_param(vec![challenge]))
+        .expect("sync challenge");
+    assert_eq!(event, SyncEvent::WaitChallenge);
+    (challenge_context, block2, block3)
+}
+
+/// The revert action of the challenge, `reverted_blocks` are recorded in the reverted block root
+pub fn revert(
+    header_number: u64,
+    context: &StartChallenge,
+    reverted_blocks: &[&L2Block],
+) -> L1Action {
+    let mut reverted_block_smt: SMT<DefaultStore<H256>> = Default::default();
+    for block in reverted_blocks {
+        reverted_block_smt
+            .update(block.hash().into(), H256::one())
+            .expect("update reverted block");
+    }
+    let reverted_block_root: [u8; 32] = (*reverted_block_smt.root()).into();
+    let global_state = global_state(Status::Running)
+        .as_builder()
+        .reverted_block_root(reverted_block_root.pack())
+        .build();
+    L1Action {
+        transaction: build_rollup_tx(header_number as u32, global_state.as_slice(), None),
+        header_info: header_info(header_number),
+        context: L1ActionContext::Revert {
+            context: context.clone(),
+        },
+    }
+}
+
+#[test]
+fn test_revert_bad_block_after_challenge() {
+    let mut chain = setup_chain();
+    assert_eq!(*chain.store().reverted_block_smt().root(), H256::zero());
+    let (challenge_context, block2, block3) = sync_challenged_bad_block(&mut chain);
+    let block1_hash = chain.local_state().tip().hash();
+    let block1_account_root = *chain.store().account_smt().root();
+
+    let event = chain
+        .sync(sync_param(vec![revert(
+            5,
+            &challenge_context,
+            &[&block2, &block3],
+        )]))
+        .expect("sync revert");
+    assert_eq!(event, SyncEvent::Success);
+    assert!(chain.bad_block_context.is_none());
+    assert_eq!(chain.local_state().status(), Ok(Status::Running));
+    // the layer2 state is reverted to block 1
+    assert_eq!(chain.local_state().tip().hash(), block1_hash);
+    assert_eq!(*chain.store().account_smt().root(), block1_account_root);
+    for block in &[&block2, &block3] {
+        assert!(chain
+            .store()
+            .is_block_reverted(&block.hash().into())
+            .expect("is reverted"));
+    }
+
+    // the rollup continues from block 1
+    let block2 = build_block(2, Vec::new());
+    let event = chain
+        .sync(sync_param(vec![submit_block(6, &block2)]))
+        .expect("sync new block 2");
+    assert_eq!(event, SyncEvent::Success);
+    assert_eq!(chain.local_state().tip().hash(), block2.hash());
+}
+
+#[test]
+fn test_revert_with_mismatched_reverted_block_root() {
+    let mut chain = setup_chain();
+    let (challenge_context, block2, block3) = sync_challenged_bad_block(&mut chain);
+    let block1_hash = chain.local_state().tip().hash();
+
+    // block 3 is missing in the reverted block root of layer1
+    let result = chain.sync(sync_param(vec![revert(5, &challenge_context, &[&block2])]));
+    assert!(result.is_err());
+    // nothing is reverted
+    assert!(chain.bad_block_context.is_some());
+    assert_eq!(chain.local_state().status(), Ok(Status::Halting));
+    assert_eq!(chain.local_state().tip().hash(), block1_hash);
+    assert_eq!(*chain.store().reverted_block_smt().root(), H256::zero());
+    assert!(!chain
+        .store()
+        .is_block_reverted(&block2.hash().into())
+        .expect("is reverted"));
+
+    // the consistent revert is applied
+    let event = chain
+        .sync(sync_param(vec![revert(
+            5,
+            &challenge_context,
+            &[&block2, &block3],
+        )]))
+        .expect("sync revert");
+    assert_eq!(event, SyncEvent::Success);
+    assert!(chain.bad_block_context.is_none());
+}
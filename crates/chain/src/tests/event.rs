@@ -0,0 +1,54 @@
+use super::{
+    challenge::{revert, sync_challenged_bad_block},
+    setup_chain,
+    sync::sync_param,
+};
+use crate::{chain::SyncEvent, event::ChainEvent};
+use gw_common::H256;
+use gw_types::{core::Status, prelude::*};
+
+#[test]
+fn test_subscribe_challenge_events() {
+    let mut chain = setup_chain();
+    let receiver = chain.subscribe();
+
+    let (challenge_context, block2, block3) = sync_challenged_bad_block(&mut chain);
+    let block1 = chain.local_state().tip().clone();
+    let event = chain
+        .sync(sync_param(vec![revert(
+            5,
+            &challenge_context,
+            &[&block2, &block3],
+        )]))
+        .expect("sync revert");
+    assert_eq!(event, SyncEvent::Success);
+
+    let events: Vec<ChainEvent> = receiver.try_iter().collect();
+    assert_eq!(events.len(), 5, "events {:?}", events);
+    // the bad block 2 and block 3 are not attached
+    match &events[0] {
+        ChainEvent::NewTip { block } => assert_eq!(block.hash(), block1.hash()),
+        event => panic!("unexpected event {:?}", event),
+    }
+    match &events[1] {
+        ChainEvent::ChallengeStarted { context } => {
+            assert_eq!(context.as_slice(), challenge_context.as_slice())
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    match &events[2] {
+        ChainEvent::StatusChanged { status } => assert_eq!(*status, Status::Halting),
+        event => panic!("unexpected event {:?}", event),
+    }
+    match &events[3] {
+        ChainEvent::BlockReverted { block_hashes } => assert_eq!(
+            block_hashes,
+            &vec![H256::from(block2.hash()), H256::from(block3.hash())]
+        ),
+        event => panic!("unexpected event {:?}", event),
+    }
+    match &events[4] {
+        ChainEvent::StatusChanged { status } => assert_eq!(*status, Status::Running),
+        event => panic!("unexpected event {:?}", event),
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    chain::{Chain, TxPoolImpl},
+    deposition::InputCell,
+    next_block_context::NextBlockContext,
+    tx_pool::TxPool,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::ScriptHashType,
+    packed::{CellInput, CellOutput, OutPoint, RawTransaction, Script, Transaction, WitnessArgs},
+    prelude::*,
+};
+use gw_config::{ChainConfig, GeneratorConfig, GenesisConfig, TxPoolConfig};
+use gw_generator::{
+    account_lock_manage::AccountLockManage, backend_manage::BackendManage, Generator,
+};
+use gw_store::{genesis::build_genesis, Store};
+use gw_types::packed::HeaderInfo;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+mod challenge;
+mod event;
+mod sync;
+mod tx_pool;
+
+fn new_generator() -> Generator {
+    Generator::new(
+        BackendManage::default(),
+        AccountLockManage::default(),
+        GeneratorConfig::default(),
+    )
+}
+
+pub fn new_tx_pool(store: &Store<crate::chain::StateStore>) -> TxPoolImpl {
+    let tip = store.get_tip_block().expect("tip").expect("tip exists");
+    TxPool::create(
+        TxPoolConfig::default(),
+        store.new_overlay().expect("overlay"),
+        new_generator(),
+        &tip,
+        NextBlockContext {
+            aggregator_id: 0,
+            timestamp: 0,
+        },
+        None,
+    )
+    .expect("create tx pool")
+}
+
+pub fn rollup_type_script() -> Script {
+    Script::new_builder()
+        .code_hash([1u8; 32].pack())
+        .args(Bytes::from(vec![1u8; 32]).pack())
+        .build()
+}
+
+pub fn deposition_lock() -> Script {
+    Script::new_builder()
+        .code_hash([2u8; 32].pack())
+        .hash_type(ScriptHashType::Type.into())
+        .build()
+}
+
+/// Create a chain from the genesis
+pub fn setup_chain() -> Chain {
+    let genesis = build_genesis(&GenesisConfig { timestamp: 0 }).expect("genesis");
+    let mut store: Store<crate::chain::StateStore> = Default::default();
+    store
+        .init_genesis(genesis, HeaderInfo::default())
+        .expect("init genesis");
+    let tx_pool = new_tx_pool(&store);
+    Chain::create(
+        ChainConfig {
+            rollup_type_script: rollup_type_script(),
+            deposition_lock: deposition_lock(),
+            l1_sudt_type: Script::new_builder()
+                .code_hash([3u8; 32].pack())
+                .hash_type(ScriptHashType::Type.into())
+                .build(),
+        },
+        store,
+        new_generator(),
+        Arc::new(Mutex::new(tx_pool)),
+        None,
+    )
+    .expect("create chain")
+}
+
+/// Build a layer1 transaction which updates the rollup cell
+///
+/// `global_state` is the data of the rollup cell, `l2block` is put in the witness,
+/// the input rollup cell is indexed by `input_index` to distinguish the txs.
+pub fn build_rollup_tx(
+    input_index: u32,
+    global_state: &[u8],
+    l2block: Option<&[u8]>,
+) -> Transaction {
+    let input = CellInput::new_builder()
+        .previous_output(OutPoint::new_builder().index(input_index.pack()).build())
+        .build();
+    let rollup_cell = CellOutput::new_builder()
+        .type_(Some(rollup_type_script()).pack())
+        .build();
+    let raw = RawTransaction::new_builder()
+        .inputs(vec![input].pack())
+        .outputs(vec![rollup_cell].pack())
+        .outputs_data(vec![Bytes::from(global_state.to_vec())].pack())
+        .build();
+    let witness = WitnessArgs::new_builder()
+        .output_type(l2block.map(|block| Bytes::from(block.to_vec())).pack())
+        .build();
+    Transaction::new_builder()
+        .raw(raw)
+        .witnesses(vec![witness.as_bytes()].pack())
+        .build()
+}
+
+/// The resolved input of the rollup tx built by `build_rollup_tx`
+pub fn rollup_input_cell() -> InputCell {
+    InputCell {
+        output: CellOutput::new_builder().build(),
+        data: Bytes::new(),
+    }
+}
+
+/// A CKB deposition cell of the rollup, `lock_args` is the molecule `DepositionLockArgs`
+pub fn deposition_cell(capacity: u64, lock_args: &[u8]) -> InputCell {
+    let mut args = rollup_type_script().calc_script_hash().as_slice().to_vec();
+    args.extend_from_slice(lock_args);
+    let lock = deposition_lock()
+        .as_builder()
+        .args(Bytes::from(args).pack())
+        .build();
+    InputCell {
+        output: CellOutput::new_builder()
+            .capacity(capacity.pack())
+            .lock(lock)
+            .build(),
+        data: Bytes::new(),
+    }
+}
@@ -0,0 +1,316 @@
+use super::{build_rollup_tx, deposition_cell, rollup_input_cell, setup_chain};
+use crate::chain::{L1Action, L1ActionContext, SyncEvent, SyncParam};
+use crate::next_block_context::NextBlockContext;
+use gw_common::{CKB_SUDT_SCRIPT_HASH, FINALIZE_BLOCKS, H256};
+use gw_types::{
+    core::Status,
+    packed::{
+        CancelChallenge, DepositionLockArgs, GlobalState, HeaderInfo, L2Block, L2Transaction,
+        RawL2Block, RawL2Transaction, RawWithdrawalRequest, Script, WithdrawalRequest,
+    },
+    prelude::*,
+};
+
+pub fn global_state(status: Status) -> GlobalState {
+    GlobalState::new_builder()
+        .status((status as u8).into())
+        .build()
+}
+
+pub fn header_info(number: u64) -> HeaderInfo {
+    HeaderInfo::new_builder().number(number.pack()).build()
+}
+
+pub fn build_block(number: u64, txs: Vec<L2Transaction>) -> L2Block {
+    let raw = RawL2Block::new_builder().number(number.pack()).build();
+    L2Block::new_builder()
+        .raw(raw)
+        .transactions(txs.pack())
+        .build()
+}
+
+pub fn submit_block(header_number: u64, l2block: &L2Block) -> L1Action {
+    let global_state = global_state(Status::Running);
+    L1Action {
+        transaction: build_rollup_tx(
+            header_number as u32,
+            global_state.as_slice(),
+            Some(l2block.as_slice()),
+        ),
+        header_info: header_info(header_number),
+        context: L1ActionContext::SubmitTxs {
+            input_cells: vec![rollup_input_cell()],
+        },
+    }
+}
+
+/// The layer2 lock of the deposition cells
+pub fn depositor_script() -> Script {
+    Script::new_builder()
+        .code_hash([4u8; 32].pack())
+        .args(vec![5u8; 20].pack())
+        .build()
+}
+
+/// Submit a block with a CKB deposition, the input of the rollup tx is resolved to the deposition cell
+pub fn submit_block_with_deposition(
+    header_number: u64,
+    l2block: &L2Block,
+    capacity: u64,
+) -> L1Action {
+    let lock_args = DepositionLockArgs::new_builder()
+        .layer2_lock(depositor_script())
+        .build();
+    let mut action = submit_block(header_number, l2block);
+    action.context = L1ActionContext::SubmitTxs {
+        input_cells: vec![deposition_cell(capacity, lock_args.as_slice())],
+    };
+    action
+}
+
+/// The reserved account has no backend, the tx is failed to execute locally
+pub fn bad_tx() -> L2Transaction {
+    L2Transaction::new_builder()
+        .raw(
+            RawL2Transaction::new_builder()
+                .from_id(0u32.pack())
+                .to_id(0u32.pack())
+                .nonce(0u32.pack())
+                .build(),
+        )
+        .build()
+}
+
+/// Submit a block and move the finalized boundary to `FINALIZE_BLOCKS` blocks before it
+fn submit_finalizing_block(header_number: u64, l2block: &L2Block) -> L1Action {
+    let number: u64 = l2block.raw().number().unpack();
+    let global_state = global_state(Status::Running)
+        .as_builder()
+        .last_finalized_block_number(number.saturating_sub(FINALIZE_BLOCKS).pack())
+        .build();
+    let mut action = submit_block(header_number, l2block);
+    action.transaction = build_rollup_tx(
+        header_number as u32,
+        global_state.as_slice(),
+        Some(l2block.as_slice()),
+    );
+    action
+}
+
+pub fn sync_param(updates: Vec<L1Action>) -> SyncParam {
+    SyncParam {
+        reverts: Vec::new(),
+        updates,
+        next_block_context: NextBlockContext {
+            aggregator_id: 0,
+            timestamp: 0,
+        },
+    }
+}
+
+#[test]
+fn test_sync_continues_after_challenge_cancelled() {
+    let mut chain = setup_chain();
+
+    let block1 = build_block(1, vec![bad_tx()]);
+    let event = chain
+        .sync(sync_param(vec![submit_block(1, &block1)]))
+        .expect("sync block 1");
+    let challenge_context = match event {
+        SyncEvent::BadBlock(challenge_context) => challenge_context,
+        event => panic!("unexpected event {:?}", event),
+    };
+    assert_eq!(
+        challenge_context.args.block_hash().as_slice(),
+        &block1.hash()[..]
+    );
+    let tip_number: u64 = chain.local_state().tip().raw().number().unpack();
+    assert_eq!(tip_number, 0);
+
+    // we send the challenge
+    let halting = global_state(Status::Halting);
+    let challenge = L1Action {
+        transaction: build_rollup_tx(2, halting.as_slice(), None),
+        header_info: header_info(2),
+        context: L1ActionContext::Challenge {
+            context: challenge_context.args.clone(),
+        },
+    };
+    let event = chain
+        .sync(sync_param(vec![challenge]))
+        .expect("sync challenge");
+    assert_eq!(event, SyncEvent::WaitChallenge);
+
+    // layer1 cancels the challenge, then another block is submitted on top of block 1
+    let running = global_state(Status::Running);
+    let cancel_challenge = L1Action {
+        transaction: build_rollup_tx(3, running.as_slice(), None),
+        header_info: header_info(3),
+        context: L1ActionContext::CancelChallenge {
+            context: CancelChallenge::new_builder()
+                .raw_l2block(block1.raw())
+                .build(),
+        },
+    };
+    let block2 = build_block(2, Vec::new());
+    let event = chain
+        .sync(sync_param(vec![cancel_challenge, submit_block(4, &block2)]))
+        .expect("sync cancel challenge");
+    assert_eq!(event, SyncEvent::Success);
+
+    // block 1 is accepted as layer1 decided, and the sync continues
+    assert!(chain.bad_block_context.is_none());
+    let tip_number: u64 = chain.local_state().tip().raw().number().unpack();
+    assert_eq!(tip_number, 2);
+    assert_eq!(
+        chain.store().get_block_hash_by_number(1).expect("block 1"),
+        Some(H256::from(block1.hash()))
+    );
+    assert_eq!(chain.local_state().status(), Ok(Status::Running));
+}
+
+#[test]
+fn test_retry_sync_skips_applied_actions() {
+    let mut chain = setup_chain();
+    let block1 = build_block(1, Vec::new());
+    let block2 = build_block(2, Vec::new());
+    // block 3 is not the successor of block 1
+    let block3 = build_block(3, Vec::new());
+    let result = chain.sync(sync_param(vec![
+        submit_block(1, &block1),
+        submit_block(2, &block3),
+    ]));
+    assert!(result.is_err());
+    // the applied action is kept
+    let tip_number: u64 = chain.local_state().tip().raw().number().unpack();
+    assert_eq!(tip_number, 1);
+    let last_synced: u64 = chain.local_state().last_synced().number().unpack();
+    assert_eq!(last_synced, 1);
+
+    // retry from the same layer1 state, block 1 is not applied again
+    let event = chain
+        .sync(sync_param(vec![
+            submit_block(1, &block1),
+            submit_block(2, &block2),
+        ]))
+        .expect("retry sync");
+    assert_eq!(event, SyncEvent::Success);
+    let tip_number: u64 = chain.local_state().tip().raw().number().unpack();
+    assert_eq!(tip_number, 2);
+}
+
+#[test]
+fn test_sync_reorg_to_another_block() {
+    let mut chain = setup_chain();
+    let genesis_root = *chain.store().account_smt().root();
+
+    // block 1 deposits CKB to a new account
+    let block1 = build_block(1, Vec::new());
+    let submit_block1 = submit_block_with_deposition(1, &block1, 500_0000_0000);
+    chain
+        .sync(sync_param(vec![submit_block1.clone()]))
+        .expect("sync block 1");
+    assert_ne!(*chain.store().account_smt().root(), genesis_root);
+    let block1_hash = H256::from(block1.hash());
+    assert!(chain.store().has_block_state_journal(&block1_hash));
+
+    // layer1 reorg, another block 1 without deposition is submitted
+    let forked_block1 = RawL2Block::new_builder()
+        .number(1u64.pack())
+        .timestamp(1u64.pack())
+        .build();
+    let forked_block1 = L2Block::new_builder().raw(forked_block1).build();
+    assert_ne!(forked_block1.hash(), block1.hash());
+    let event = chain
+        .sync(SyncParam {
+            reverts: vec![submit_block1],
+            ..sync_param(vec![submit_block(1, &forked_block1)])
+        })
+        .expect("sync reorg");
+    assert_eq!(event, SyncEvent::Success);
+
+    assert_eq!(chain.local_state().tip().hash(), forked_block1.hash());
+    assert_eq!(
+        chain
+            .store()
+            .get_tip_block()
+            .expect("tip")
+            .map(|b| b.hash()),
+        Some(forked_block1.hash())
+    );
+    assert_eq!(
+        chain.store().get_block_hash_by_number(1).expect("block 1"),
+        Some(H256::from(forked_block1.hash()))
+    );
+    // the deposition is reverted with the detached block
+    assert_eq!(*chain.store().account_smt().root(), genesis_root);
+    assert!(!chain.store().has_block_state_journal(&block1_hash));
+    assert!(chain
+        .store()
+        .has_block_state_journal(&forked_block1.hash().into()));
+}
+
+#[test]
+fn test_unlockable_withdrawals_after_finalization() {
+    let mut chain = setup_chain();
+    let block1 = build_block(1, Vec::new());
+    chain
+        .sync(sync_param(vec![submit_block_with_deposition(
+            1,
+            &block1,
+            500_0000_0000,
+        )]))
+        .expect("sync block 1");
+
+    // block 2 withdraws from the deposited account
+    let raw = RawWithdrawalRequest::new_builder()
+        .nonce(0u32.pack())
+        .capacity(100_0000_0000u64.pack())
+        .sudt_script_hash(CKB_SUDT_SCRIPT_HASH.pack())
+        .account_script_hash(depositor_script().hash().pack())
+        .build();
+    let withdrawal = WithdrawalRequest::new_builder().raw(raw).build();
+    let block2 = build_block(2, Vec::new())
+        .as_builder()
+        .withdrawal_requests(vec![withdrawal.clone()].pack())
+        .build();
+    let mut updates = vec![submit_finalizing_block(2, &block2)];
+    updates.extend(
+        (3..=FINALIZE_BLOCKS + 1)
+            .map(|number| submit_finalizing_block(number, &build_block(number, Vec::new()))),
+    );
+    let event = chain.sync(sync_param(updates)).expect("sync blocks");
+    assert_eq!(event, SyncEvent::Success);
+    // block 1 is finalized without withdrawals
+    assert_eq!(chain.store().get_last_finalized_block_number(), 1);
+    assert!(!chain
+        .store()
+        .is_finalized(&block2.hash().into())
+        .expect("is finalized"));
+    assert!(chain.take_unlockable_withdrawals().is_empty());
+
+    let number = FINALIZE_BLOCKS + 2;
+    chain
+        .sync(sync_param(vec![submit_finalizing_block(
+            number,
+            &build_block(number, Vec::new()),
+        )]))
+        .expect("sync block");
+    assert_eq!(chain.store().get_last_finalized_block_number(), 2);
+    assert!(chain
+        .store()
+        .is_finalized(&block2.hash().into())
+        .expect("is finalized"));
+    let withdrawals = chain.take_unlockable_withdrawals();
+    assert_eq!(withdrawals.len(), 1);
+    assert_eq!(withdrawals[0].block_number, 2);
+    assert_eq!(withdrawals[0].block_hash, H256::from(block2.hash()));
+    assert_eq!(withdrawals[0].request.as_slice(), withdrawal.as_slice());
+    // the taken withdrawals are pruned
+    assert!(chain
+        .store()
+        .get_unlockable_withdrawals(2)
+        .expect("unlockable withdrawals")
+        .is_none());
+    assert!(chain.take_unlockable_withdrawals().is_empty());
+}
@@ -0,0 +1,590 @@
+use crate::chain::{StateStore, TxPoolImpl};
+use crate::custodian::{CustodianLiquidity, CustodianProvider};
+use crate::next_block_context::NextBlockContext;
+use crate::tx_journal::TxJournal;
+use crate::tx_pool::TxPool;
+use anyhow::Result;
+use gw_common::{
+    builtins::CKB_SUDT_ACCOUNT_ID, h256_ext::H256Ext, state::State, CKB_SUDT_SCRIPT_HASH, H256,
+    SUDT_CODE_HASH,
+};
+use gw_config::{GeneratorConfig, GenesisConfig, TxPoolConfig};
+use gw_generator::{
+    account_lock_manage::{always_success::AlwaysSuccess, AccountLockManage},
+    backend_manage::{Backend, BackendManage},
+    traits::StateExt,
+    Generator,
+};
+use gw_store::{genesis::build_genesis, OverlayStore, Store, WrapStore};
+use gw_types::{
+    bytes::Bytes,
+    packed::{
+        BlockInfo, HeaderInfo, L2Block, L2Transaction, RawL2Block, RawL2Transaction,
+        RawWithdrawalRequest, SUDTArgs, SUDTTransfer, Script, WithdrawalRequest,
+    },
+    prelude::*,
+};
+use std::{fs::OpenOptions, io::Write};
+
+const SUDT_GENERATOR_PATH: &str = "../../c/build/sudt-generator";
+const ALWAYS_SUCCESS_CODE_HASH: [u8; 32] = [4u8; 32];
+const A_ARGS: u8 = 1;
+const B_ARGS: u8 = 2;
+const INIT_BALANCE: u128 = 1000_0000_0000;
+const CUSTODIAN_CAPACITY: u64 = 10000_0000_0000;
+
+type PoolState = OverlayStore<WrapStore<StateStore>>;
+
+struct FixedCustodianProvider(CustodianLiquidity);
+
+impl CustodianProvider for FixedCustodianProvider {
+    fn query_liquidity(&self) -> Result<CustodianLiquidity> {
+        Ok(self.0.clone())
+    }
+}
+
+fn account_script(args: u8) -> Script {
+    Script::new_builder()
+        .code_hash(ALWAYS_SUCCESS_CODE_HASH.pack())
+        .args(vec![args; 20].pack())
+        .build()
+}
+
+/// Returns the tip, the tip state and ids of two accounts which own CKB
+fn setup_state() -> (L2Block, PoolState, u32, u32) {
+    let genesis = build_genesis(&GenesisConfig { timestamp: 0 }).expect("genesis");
+    let mut store: Store<StateStore> = Default::default();
+    store
+        .init_genesis(genesis, HeaderInfo::default())
+        .expect("init genesis");
+    let tip = store.get_tip_block().expect("tip").expect("tip exists");
+    let mut state = store.new_overlay().expect("overlay");
+    let mut create_account = |args: u8| {
+        let id = state
+            .create_account_from_script(account_script(args))
+            .expect("create account");
+        state
+            .update_value(
+                CKB_SUDT_ACCOUNT_ID,
+                &H256::from_u32(id),
+                H256::from_u128(INIT_BALANCE),
+            )
+            .expect("init balance");
+        id
+    };
+    let a_id = create_account(A_ARGS);
+    let b_id = create_account(B_ARGS);
+    (tip, state, a_id, b_id)
+}
+
+/// Backends of the CKB sUDT account created in the genesis
+fn new_backend_manage() -> BackendManage {
+    let sudt_generator: Bytes = std::fs::read(SUDT_GENERATOR_PATH)
+        .expect("read sUDT generator")
+        .into();
+    let mut backend_manage = BackendManage::default();
+    backend_manage.register_backend(Backend {
+        validator: sudt_generator.clone(),
+        generator: sudt_generator,
+        validator_code_hash: SUDT_CODE_HASH.into(),
+    });
+    backend_manage
+}
+
+fn new_generator(config: GeneratorConfig) -> Generator {
+    let mut account_lock_manage = AccountLockManage::default();
+    account_lock_manage
+        .register_lock_algorithm(ALWAYS_SUCCESS_CODE_HASH.into(), Box::new(AlwaysSuccess));
+    Generator::new(new_backend_manage(), account_lock_manage, config)
+}
+
+fn create_tx_pool(
+    config: TxPoolConfig,
+    generator_config: GeneratorConfig,
+    tip: &L2Block,
+    state: PoolState,
+) -> Result<TxPoolImpl> {
+    TxPool::create(
+        config,
+        state,
+        new_generator(generator_config),
+        tip,
+        NextBlockContext {
+            aggregator_id: 0,
+            timestamp: 0,
+        },
+        Some(Box::new(FixedCustodianProvider(CustodianLiquidity {
+            capacity: CUSTODIAN_CAPACITY,
+            sudt_amounts: Default::default(),
+        }))),
+    )
+}
+
+fn new_tx_pool(config: TxPoolConfig, tip: &L2Block, state: PoolState) -> TxPoolImpl {
+    create_tx_pool(config, GeneratorConfig::default(), tip, state).expect("create tx pool")
+}
+
+/// Build a CKB transfer tx
+fn transfer_tx(from_id: u32, to_id: u32, nonce: u32, amount: u128, fee: u128) -> L2Transaction {
+    let args = SUDTArgs::new_builder()
+        .set(
+            SUDTTransfer::new_builder()
+                .to(to_id.pack())
+                .amount(amount.pack())
+                .fee(fee.pack())
+                .build(),
+        )
+        .build();
+    let raw = RawL2Transaction::new_builder()
+        .from_id(from_id.pack())
+        .to_id(CKB_SUDT_ACCOUNT_ID.pack())
+        .nonce(nonce.pack())
+        .args(args.as_bytes().pack())
+        .build();
+    L2Transaction::new_builder().raw(raw).build()
+}
+
+/// Build a CKB withdrawal request
+fn withdrawal_request(account_args: u8, nonce: u32) -> WithdrawalRequest {
+    let raw = RawWithdrawalRequest::new_builder()
+        .nonce(nonce.pack())
+        .capacity(100_0000_0000u64.pack())
+        .sudt_script_hash(CKB_SUDT_SCRIPT_HASH.pack())
+        .account_script_hash(account_script(account_args).hash().pack())
+        .build();
+    WithdrawalRequest::new_builder().raw(raw).build()
+}
+
+fn packaged_tx_hashes(tx_pool: &mut TxPoolImpl) -> Vec<[u8; 32]> {
+    tx_pool
+        .package(&[])
+        .expect("package")
+        .tx_receipts
+        .into_iter()
+        .map(|(tx, _receipt)| tx.hash())
+        .collect()
+}
+
+#[test]
+fn test_keep_pending_txs_on_update_tip() {
+    let (tip, state, a_id, b_id) = setup_state();
+    let mut tx_pool = new_tx_pool(TxPoolConfig::default(), &tip, state.clone());
+
+    let tx0 = transfer_tx(a_id, b_id, 0, 100, 1);
+    let tx1 = transfer_tx(a_id, b_id, 1, 100, 1);
+    let b_tx0 = transfer_tx(b_id, a_id, 0, 100, 1);
+    let b_tx1 = transfer_tx(b_id, a_id, 1, 100, 1);
+    for tx in &[tx0.clone(), tx1.clone(), b_tx0, b_tx1.clone()] {
+        tx_pool
+            .push(tx.clone())
+            .expect("push")
+            .expect("tx is executed");
+    }
+
+    // another aggregator's block includes tx0 and a different tx of b with nonce 0
+    let other_b_tx0 = transfer_tx(b_id, a_id, 0, 50, 1);
+    let block_txs = vec![tx0, other_b_tx0];
+    let generator = new_generator(GeneratorConfig::default());
+    let block_info = BlockInfo::new_builder().number(1u64.pack()).build();
+    let mut tip_state = state;
+    for tx in &block_txs {
+        let run_result = generator
+            .execute(&tip_state, &block_info, &tx.raw())
+            .expect("execute");
+        tip_state.apply_run_result(&run_result).expect("apply");
+    }
+    let new_tip = L2Block::new_builder()
+        .raw(RawL2Block::new_builder().number(1u64.pack()).build())
+        .transactions(block_txs.pack())
+        .build();
+    tx_pool
+        .update_tip(
+            &new_tip,
+            tip_state,
+            NextBlockContext {
+                aggregator_id: 0,
+                timestamp: 0,
+            },
+        )
+        .expect("update tip");
+
+    // the included tx is removed and b_tx0 is dropped since its nonce is taken,
+    // the other txs are still valid on the new tip
+    assert_eq!(
+        packaged_tx_hashes(&mut tx_pool),
+        vec![tx1.hash(), b_tx1.hash()]
+    );
+}
+
+#[test]
+fn test_withdrawal_request_nonce() {
+    let (tip, state, a_id, b_id) = setup_state();
+    let mut tx_pool = new_tx_pool(TxPoolConfig::default(), &tip, state);
+
+    // the pending tx has taken the nonce
+    let a_tx0 = transfer_tx(a_id, b_id, 0, 100, 1);
+    tx_pool.push(a_tx0.clone()).expect("push a_tx0");
+    assert!(tx_pool
+        .push_withdrawal_request(withdrawal_request(A_ARGS, 0))
+        .is_err());
+    assert!(tx_pool
+        .push_withdrawal_request(withdrawal_request(A_ARGS, 1))
+        .is_err());
+
+    // requests with consecutive nonces are accepted, txs follow them
+    tx_pool
+        .push_withdrawal_request(withdrawal_request(B_ARGS, 0))
+        .expect("push withdrawal 0");
+    assert!(tx_pool
+        .push_withdrawal_request(withdrawal_request(B_ARGS, 0))
+        .is_err());
+    tx_pool
+        .push_withdrawal_request(withdrawal_request(B_ARGS, 1))
+        .expect("push withdrawal 1");
+    assert!(tx_pool.push(transfer_tx(b_id, a_id, 0, 100, 1)).is_err());
+    let b_tx2 = transfer_tx(b_id, a_id, 2, 100, 1);
+    tx_pool
+        .push(b_tx2.clone())
+        .expect("push b_tx2")
+        .expect("tx is executed");
+
+    let pkg = tx_pool.package(&[]).expect("package");
+    let withdrawal_nonces: Vec<u32> = pkg
+        .withdrawal_requests
+        .iter()
+        .map(|request| request.raw().nonce().unpack())
+        .collect();
+    assert_eq!(withdrawal_nonces, vec![0, 1]);
+    let tx_hashes: Vec<_> = pkg
+        .tx_receipts
+        .into_iter()
+        .map(|(tx, _receipt)| tx.hash())
+        .collect();
+    assert_eq!(tx_hashes, vec![a_tx0.hash(), b_tx2.hash()]);
+}
+
+#[test]
+fn test_package_txs_by_fee() {
+    let (tip, state, a_id, b_id) = setup_state();
+    let mut tx_pool = new_tx_pool(TxPoolConfig::default(), &tip, state);
+
+    let a_tx0 = transfer_tx(a_id, b_id, 0, 100, 1);
+    let a_tx1 = transfer_tx(a_id, b_id, 1, 100, 10);
+    let b_tx0 = transfer_tx(b_id, a_id, 0, 100, 5);
+    let b_tx1 = transfer_tx(b_id, a_id, 1, 100, 2);
+    for tx in &[a_tx0.clone(), a_tx1.clone(), b_tx0.clone(), b_tx1.clone()] {
+        tx_pool
+            .push(tx.clone())
+            .expect("push")
+            .expect("tx is executed");
+    }
+
+    // a_tx1 pays the highest fee, but it must follow a_tx0
+    assert_eq!(
+        packaged_tx_hashes(&mut tx_pool),
+        vec![b_tx0.hash(), b_tx1.hash(), a_tx0.hash(), a_tx1.hash()]
+    );
+}
+
+#[test]
+fn test_evict_lowest_fee_tx() {
+    let (tip, state, a_id, b_id) = setup_state();
+    let config = TxPoolConfig {
+        max_pool_txs: 2,
+        max_packaged_txs: 2,
+        ..Default::default()
+    };
+    let mut tx_pool = new_tx_pool(config, &tip, state);
+
+    let a_tx0 = transfer_tx(a_id, b_id, 0, 100, 2);
+    let b_tx0 = transfer_tx(b_id, a_id, 0, 100, 1);
+    for tx in &[a_tx0.clone(), b_tx0] {
+        tx_pool
+            .push(tx.clone())
+            .expect("push")
+            .expect("tx is executed");
+    }
+    // the pool is full, a tx must pay more than the lowest fee tx of other senders
+    assert!(tx_pool.push(transfer_tx(b_id, a_id, 1, 100, 1)).is_err());
+    let a_tx1 = transfer_tx(a_id, b_id, 1, 100, 3);
+    tx_pool
+        .push(a_tx1.clone())
+        .expect("push a_tx1")
+        .expect("tx is executed");
+
+    // b_tx0 is evicted
+    assert_eq!(
+        packaged_tx_hashes(&mut tx_pool),
+        vec![a_tx0.hash(), a_tx1.hash()]
+    );
+}
+
+#[test]
+fn test_promote_future_txs() {
+    let (tip, state, a_id, b_id) = setup_state();
+    let mut tx_pool = new_tx_pool(TxPoolConfig::default(), &tip, state);
+
+    let tx2 = transfer_tx(a_id, b_id, 2, 100, 1);
+    let tx1 = transfer_tx(a_id, b_id, 1, 100, 1);
+    assert!(tx_pool.push(tx2.clone()).expect("queue tx2").is_none());
+    assert!(tx_pool.push(tx1.clone()).expect("queue tx1").is_none());
+    // nonce is too far in the future
+    let max_queued = TxPoolConfig::default().max_queued_txs_per_sender;
+    assert!(tx_pool
+        .push(transfer_tx(a_id, b_id, max_queued, 100, 1))
+        .is_err());
+    // queued txs are not packaged until the nonce gap is filled
+    assert!(packaged_tx_hashes(&mut tx_pool).is_empty());
+
+    let tx0 = transfer_tx(a_id, b_id, 0, 100, 1);
+    tx_pool
+        .push(tx0.clone())
+        .expect("push tx0")
+        .expect("tx is executed");
+    assert_eq!(
+        packaged_tx_hashes(&mut tx_pool),
+        vec![tx0.hash(), tx1.hash(), tx2.hash()]
+    );
+}
+
+#[test]
+fn test_replace_by_fee() {
+    let (tip, state, a_id, b_id) = setup_state();
+    let mut tx_pool = new_tx_pool(TxPoolConfig::default(), &tip, state);
+
+    let tx = transfer_tx(a_id, b_id, 0, 100, 1);
+    tx_pool
+        .push(tx.clone())
+        .expect("push")
+        .expect("tx is executed");
+    // the replacement must pay a higher fee
+    let same_fee_tx = transfer_tx(a_id, b_id, 0, 200, 1);
+    assert!(tx_pool.push(same_fee_tx).is_err());
+    let next_tx = transfer_tx(a_id, b_id, 1, 100, 1);
+    tx_pool
+        .push(next_tx.clone())
+        .expect("push")
+        .expect("tx is executed");
+    let replacement_tx = transfer_tx(a_id, b_id, 0, 200, 2);
+    tx_pool
+        .push(replacement_tx.clone())
+        .expect("replace")
+        .expect("tx is executed");
+
+    // the following tx is re-executed after the replacement
+    assert_eq!(
+        packaged_tx_hashes(&mut tx_pool),
+        vec![replacement_tx.hash(), next_tx.hash()]
+    );
+}
+
+#[test]
+fn test_reject_invalid_config() {
+    let (tip, state, _a_id, _b_id) = setup_state();
+    let invalid_configs = vec![
+        (
+            TxPoolConfig {
+                max_pool_txs: 10,
+                max_packaged_txs: 11,
+                ..Default::default()
+            },
+            GeneratorConfig::default(),
+        ),
+        (
+            TxPoolConfig {
+                max_txs_per_sender: 0,
+                ..Default::default()
+            },
+            GeneratorConfig::default(),
+        ),
+        (
+            TxPoolConfig {
+                max_block_data_bytes: 100,
+                max_tx_data_bytes: 101,
+                ..Default::default()
+            },
+            GeneratorConfig::default(),
+        ),
+        (
+            TxPoolConfig {
+                max_block_cycles: 1000,
+                ..Default::default()
+            },
+            GeneratorConfig {
+                max_cycles: 1001,
+                ..Default::default()
+            },
+        ),
+        (
+            TxPoolConfig::default(),
+            GeneratorConfig {
+                execution_threads: 0,
+                ..Default::default()
+            },
+        ),
+    ];
+    for (config, generator_config) in invalid_configs {
+        assert!(create_tx_pool(config, generator_config, &tip, state.clone()).is_err());
+    }
+}
+
+#[test]
+fn test_configured_limits() {
+    let (tip, state, a_id, b_id) = setup_state();
+    let config = TxPoolConfig {
+        max_packaged_txs: 1,
+        max_txs_per_sender: 1,
+        max_queued_txs_per_sender: 2,
+        ..Default::default()
+    };
+    let generator_config = GeneratorConfig {
+        min_withdrawal_capacity: 200_0000_0000,
+        execution_threads: 1,
+        ..Default::default()
+    };
+    let mut tx_pool =
+        create_tx_pool(config, generator_config, &tip, state).expect("create tx pool");
+
+    let a_tx0 = transfer_tx(a_id, b_id, 0, 100, 1);
+    tx_pool
+        .push(a_tx0.clone())
+        .expect("push a_tx0")
+        .expect("tx is executed");
+    // too many pending txs of the sender
+    assert!(tx_pool.push(transfer_tx(a_id, b_id, 1, 100, 1)).is_err());
+    // the future queue of the sender is limited
+    assert!(tx_pool
+        .push(transfer_tx(a_id, b_id, 2, 100, 1))
+        .expect("queue a_tx2")
+        .is_none());
+    assert!(tx_pool.push(transfer_tx(a_id, b_id, 3, 100, 1)).is_err());
+    // the withdrawal capacity is less than the configured minimum
+    assert!(tx_pool
+        .push_withdrawal_request(withdrawal_request(B_ARGS, 0))
+        .is_err());
+
+    // one tx is packaged per block
+    let b_tx0 = transfer_tx(b_id, a_id, 0, 100, 1);
+    tx_pool
+        .push(b_tx0.clone())
+        .expect("push b_tx0")
+        .expect("tx is executed");
+    assert_eq!(packaged_tx_hashes(&mut tx_pool), vec![a_tx0.hash()]);
+    assert_eq!(packaged_tx_hashes(&mut tx_pool), vec![b_tx0.hash()]);
+}
+
+#[test]
+fn test_replay_journal() {
+    let (tip, state, a_id, b_id) = setup_state();
+    let journal_path =
+        std::env::temp_dir().join(format!("gw-tx-pool-journal-{}", std::process::id()));
+    let _ = std::fs::remove_file(&journal_path);
+    let config = TxPoolConfig {
+        journal_path: Some(journal_path.clone()),
+        ..Default::default()
+    };
+
+    let tx0 = transfer_tx(a_id, b_id, 0, 100, 1);
+    let tx2 = transfer_tx(a_id, b_id, 2, 100, 1);
+    let b_tx0 = transfer_tx(b_id, a_id, 0, 100, 1);
+    {
+        let mut tx_pool = new_tx_pool(config.clone(), &tip, state.clone());
+        tx_pool.push(tx0.clone()).expect("push tx0");
+        tx_pool.push(tx2.clone()).expect("push tx2");
+        tx_pool.push(b_tx0.clone()).expect("push b_tx0");
+    }
+    let journal_len = std::fs::metadata(&journal_path).expect("journal").len();
+    // simulate a crash while appending a record
+    {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&journal_path)
+            .expect("open journal");
+        let truncated_tx = transfer_tx(a_id, b_id, 1, 100, 1);
+        let data = truncated_tx.as_slice();
+        file.write_all(&[0u8]).expect("write kind");
+        file.write_all(&(data.len() as u32).to_le_bytes())
+            .expect("write len");
+        file.write_all(&data[..data.len() / 2]).expect("write data");
+    }
+    assert_eq!(TxJournal::load(&journal_path).expect("load").len(), 3);
+
+    let mut tx_pool = new_tx_pool(config, &tip, state);
+    // the truncated record is dropped by the compaction
+    assert_eq!(
+        std::fs::metadata(&journal_path).expect("journal").len(),
+        journal_len
+    );
+    let tx1 = transfer_tx(a_id, b_id, 1, 100, 1);
+    tx_pool.push(tx1.clone()).expect("push tx1");
+    assert_eq!(
+        packaged_tx_hashes(&mut tx_pool),
+        vec![tx0.hash(), b_tx0.hash(), tx1.hash(), tx2.hash()]
+    );
+    std::fs::remove_file(&journal_path).expect("remove journal");
+}
+
+#[test]
+fn test_prev_account_state_with_queued_withdrawal() {
+    let (tip, state, a_id, b_id) = setup_state();
+    let mut tx_pool = new_tx_pool(TxPoolConfig::default(), &tip, state.clone());
+    tx_pool
+        .push_withdrawal_request(withdrawal_request(B_ARGS, 0))
+        .expect("push withdrawal");
+
+    // a new tip arrives while the withdrawal request is queued
+    let tx0 = transfer_tx(a_id, b_id, 0, 100, 1);
+    let block_info = BlockInfo::new_builder().number(1u64.pack()).build();
+    let mut tip_state = state;
+    let run_result = new_generator(GeneratorConfig::default())
+        .execute(&tip_state, &block_info, &tx0.raw())
+        .expect("execute");
+    tip_state.apply_run_result(&run_result).expect("apply");
+    let tip_root = tip_state.calculate_root().expect("tip root");
+    let tip_account_count = tip_state.get_account_count().expect("tip account count");
+    let new_tip = L2Block::new_builder()
+        .raw(RawL2Block::new_builder().number(1u64.pack()).build())
+        .transactions(vec![tx0].pack())
+        .build();
+    tx_pool
+        .update_tip(
+            &new_tip,
+            tip_state,
+            NextBlockContext {
+                aggregator_id: 0,
+                timestamp: 0,
+            },
+        )
+        .expect("update tip");
+
+    // the block starts from the post state of the tip, the withdrawal is applied in the block
+    let pkg = tx_pool.package(&[]).expect("package");
+    assert_eq!(pkg.withdrawal_requests.len(), 1);
+    assert_eq!(pkg.prev_account_state.root, tip_root);
+    assert_eq!(pkg.prev_account_state.count, tip_account_count);
+    assert_ne!(pkg.post_account_state.root, tip_root);
+}
+
+#[test]
+fn test_withdrawal_without_custodian_provider() {
+    let (tip, state, _a_id, _b_id) = setup_state();
+    let mut tx_pool = TxPool::create(
+        TxPoolConfig::default(),
+        state,
+        new_generator(GeneratorConfig::default()),
+        &tip,
+        NextBlockContext {
+            aggregator_id: 0,
+            timestamp: 0,
+        },
+        None,
+    )
+    .expect("create tx pool");
+
+    // the custodian liquidity isn't checked without a provider
+    tx_pool
+        .push_withdrawal_request(withdrawal_request(B_ARGS, 0))
+        .expect("push withdrawal");
+    let pkg = tx_pool.package(&[]).expect("package");
+    assert_eq!(pkg.withdrawal_requests.len(), 1);
+}
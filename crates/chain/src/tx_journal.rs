@@ -0,0 +1,133 @@
+//! Journal of the tx pool
+//!
+//! Accepted txs and withdrawal requests are appended to a file,
+//! the tx pool replays the journal on startup to recover pending entries.
+//!
+//! Each record is encoded as `kind(1 byte) | length(4 bytes, little endian) | molecule bytes`,
+//! a truncated record at the end of the file is ignored.
+
+use anyhow::{anyhow, Result};
+use gw_types::{
+    packed::{L2Transaction, WithdrawalRequest},
+    prelude::*,
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const KIND_TRANSACTION: u8 = 0;
+const KIND_WITHDRAWAL_REQUEST: u8 = 1;
+
+#[derive(Debug, Clone)]
+pub enum JournalEntry {
+    Transaction(L2Transaction),
+    WithdrawalRequest(WithdrawalRequest),
+}
+
+pub struct TxJournal {
+    path: PathBuf,
+    file: File,
+}
+
+impl TxJournal {
+    /// Open the journal, the file is created if it doesn't exist
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| anyhow!("can't open journal {}: {}", path.to_string_lossy(), err))?;
+        Ok(TxJournal { path, file })
+    }
+
+    /// Load entries from the journal, returns an empty list if the file doesn't exist
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<JournalEntry>> {
+        let mut content = Vec::new();
+        match File::open(path.as_ref()) {
+            Ok(mut file) => {
+                file.read_to_end(&mut content)?;
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        }
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset + 5 <= content.len() {
+            let kind = content[offset];
+            let mut len_buf = [0u8; 4];
+            len_buf.copy_from_slice(&content[offset + 1..offset + 5]);
+            let len = u32::from_le_bytes(len_buf) as usize;
+            let start = offset + 5;
+            if start + len > content.len() {
+                break;
+            }
+            let data = &content[start..start + len];
+            offset = start + len;
+            let entry = match kind {
+                KIND_TRANSACTION => L2Transaction::from_slice(data)
+                    .map(JournalEntry::Transaction)
+                    .map_err(|err| anyhow!("invalid journal tx: {}", err)),
+                KIND_WITHDRAWAL_REQUEST => WithdrawalRequest::from_slice(data)
+                    .map(JournalEntry::WithdrawalRequest)
+                    .map_err(|err| anyhow!("invalid journal withdrawal request: {}", err)),
+                _ => Err(anyhow!("unknown journal entry kind {}", kind)),
+            };
+            match entry {
+                Ok(entry) => entries.push(entry),
+                Err(err) => eprintln!("skip journal entry: {}", err),
+            }
+        }
+        if offset < content.len() {
+            eprintln!(
+                "ignore truncated journal record at offset {}, journal len {}",
+                offset,
+                content.len()
+            );
+        }
+        Ok(entries)
+    }
+
+    /// Append an entry, the record is synced to the disk before returning
+    /// so an accepted entry survives a crash of the node or the OS.
+    pub fn append(&mut self, entry: &JournalEntry) -> Result<()> {
+        self.file.write_all(&encode_entry(entry))?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Replace the journal with the entries
+    ///
+    /// Entries are written to a temporary file first, then renamed to the journal.
+    pub fn rewrite(&mut self, entries: &[JournalEntry]) -> Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            for entry in entries {
+                writer.write_all(&encode_entry(entry))?;
+            }
+            writer.flush()?;
+            writer
+                .into_inner()
+                .map_err(|err| anyhow!("flush journal error: {}", err))?
+                .sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+}
+
+fn encode_entry(entry: &JournalEntry) -> Vec<u8> {
+    let (kind, data) = match entry {
+        JournalEntry::Transaction(tx) => (KIND_TRANSACTION, tx.as_slice()),
+        JournalEntry::WithdrawalRequest(request) => (KIND_WITHDRAWAL_REQUEST, request.as_slice()),
+    };
+    let mut buf = Vec::with_capacity(5 + data.len());
+    buf.push(kind);
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+    buf
+}
@@ -1,185 +1,668 @@
-use crate::crypto::{verify_signature, Signature};
+use crate::custodian::{CustodianLiquidity, CustodianProvider};
 use crate::next_block_context::NextBlockContext;
+use crate::tx_journal::{JournalEntry, TxJournal};
 use anyhow::{anyhow, Result};
 use gw_common::{
     smt::{Store, H256 as SMTH256},
     state::State,
     H256,
 };
+use gw_config::{GeneratorConfig, TxPoolConfig};
 use gw_generator::{
+    backend_manage::BackendManage,
+    parallel_executor::ParallelExecution,
     traits::{CodeStore, StateExt},
     Generator, RunResult, TxReceipt,
 };
 use gw_store::OverlayStore;
 use gw_types::{
-    packed::{BlockInfo, DepositionRequest, L2Block, L2Transaction, WithdrawalRequest},
+    packed::{
+        BlockInfo, DepositionRequest, L2Block, L2Transaction, SUDTArgs, SUDTArgsUnion,
+        WithdrawalRequest,
+    },
     prelude::*,
 };
-use std::{cmp::min, collections::HashSet};
+use std::{
+    cmp::{min, Reverse},
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque},
+};
 
-/// MAX packaged txs in a l2block
-const MAX_PACKAGED_TXS: usize = 6000;
-/// MAX packaged withdrawal in a l2block
-const MAX_PACKAGED_WITHDRAWAL: usize = 10;
-const MAX_DATA_BYTES_LIMIT: usize = 25_000;
+/// A pending tx
+struct PendingTx {
+    tx: L2Transaction,
+    sender_id: u32,
+    fee: u128,
+}
 
-/// TODO remove txs from pool if a new block already contains txs
 pub struct TxPool<S> {
+    config: TxPoolConfig,
+    /// state of the tip, pending txs are executed on top of it
+    base_state: OverlayStore<S>,
+    /// state after executing pending txs
     state: OverlayStore<S>,
     generator: Generator,
-    queue: Vec<(L2Transaction, TxReceipt)>,
+    /// pending txs in the order of execution,
+    /// txs of the same sender are always in the order of nonce
+    queue: Vec<PendingTx>,
+    /// future nonce txs of each sender, promoted to pending once the nonce gap is filled
+    future_queue: HashMap<u32, BTreeMap<u32, L2Transaction>>,
     withdrawal_queue: Vec<WithdrawalRequest>,
     next_block_info: BlockInfo,
     next_prev_account_state: MerkleState,
+    /// accepted txs and withdrawal requests are recorded to recover the pool after restart
+    journal: Option<TxJournal>,
+    /// the custodian liquidity is not checked if the provider is not set
+    custodian_provider: Option<Box<dyn CustodianProvider>>,
 }
 
-impl<S: Store<SMTH256>> TxPool<S> {
+impl<S: Store<SMTH256> + Clone + Sync> TxPool<S> {
+    /// Create a tx pool, entries of the journal are replayed against the state if the journal is configured
+    ///
+    /// If the custodian provider is set, withdrawal requests are packaged only if the custodian cells can pay them,
+    /// otherwise the custodian liquidity is not checked.
     pub fn create(
+        config: TxPoolConfig,
         state: OverlayStore<S>,
         generator: Generator,
         tip: &L2Block,
         nb_ctx: NextBlockContext,
+        custodian_provider: Option<Box<dyn CustodianProvider>>,
     ) -> Result<Self> {
-        let queue = Vec::with_capacity(MAX_PACKAGED_TXS);
-        let withdrawal_queue = Vec::with_capacity(MAX_PACKAGED_WITHDRAWAL);
+        verify_config(&config, generator.config())?;
+        let queue = Vec::with_capacity(config.max_packaged_txs as usize);
+        let withdrawal_queue = Vec::with_capacity(config.max_packaged_withdrawals as usize);
         let next_prev_account_state = get_account_state(&state)?;
         let next_block_info = gen_next_block_info(tip, nb_ctx)?;
-        Ok(TxPool {
+        let journal_path = config.journal_path.clone();
+        let mut tx_pool = TxPool {
+            config,
+            base_state: state.clone(),
             state,
             generator,
             queue,
+            future_queue: HashMap::default(),
             withdrawal_queue,
             next_block_info,
             next_prev_account_state,
-        })
+            journal: None,
+            custodian_provider,
+        };
+        if let Some(journal_path) = journal_path {
+            tx_pool.replay_journal(TxJournal::load(&journal_path)?);
+            tx_pool.journal = Some(TxJournal::open(&journal_path)?);
+            tx_pool.compact_journal();
+        }
+        Ok(tx_pool)
+    }
+
+    /// Replace backends of the generator, pending txs are executed by the new backends when they are packaged
+    pub fn set_backend_manage(&mut self, backend_manage: BackendManage) {
+        self.generator.set_backend_manage(backend_manage)
+    }
+
+    /// Push journal entries into the pool, invalid entries are dropped
+    fn replay_journal(&mut self, entries: Vec<JournalEntry>) {
+        for entry in entries {
+            let result = match entry {
+                JournalEntry::Transaction(tx) => self.push(tx).map(|_| ()),
+                JournalEntry::WithdrawalRequest(request) => self.push_withdrawal_request(request),
+            };
+            if let Err(err) = result {
+                eprintln!("drop journal entry: {}", err);
+            }
+        }
     }
 }
 
-impl<S: Store<SMTH256>> TxPool<S> {
+impl<S: Store<SMTH256> + Clone + Sync> TxPool<S> {
     /// Push a layer2 tx into pool
-    pub fn push(&mut self, tx: L2Transaction) -> Result<RunResult> {
+    ///
+    /// A tx with a future nonce is queued until prior txs of the sender are pushed,
+    /// returns `None` in this case.
+    ///
+    /// A tx with the same sender and nonce as a pending or queued tx replaces it if it pays a higher fee.
+    pub fn push(&mut self, tx: L2Transaction) -> Result<Option<RunResult>> {
+        let run_result = self.push_tx(tx.clone())?;
+        self.append_journal(JournalEntry::Transaction(tx));
+        Ok(run_result)
+    }
+
+    fn push_tx(&mut self, tx: L2Transaction) -> Result<Option<RunResult>> {
+        let raw_tx = tx.raw();
+        let sender_id: u32 = raw_tx.from_id().unpack();
+        let nonce: u32 = raw_tx.nonce().unpack();
+        let account_nonce = self.state.get_nonce(sender_id)?;
+        if nonce < account_nonce {
+            return self.replace_pending_tx(tx, account_nonce).map(Some);
+        }
+        if nonce > account_nonce {
+            self.queue_future_tx(tx, account_nonce)?;
+            return Ok(None);
+        }
+        let run_result = self.push_executable_tx(tx)?;
+        self.promote_future_txs(sender_id);
+        Ok(Some(run_result))
+    }
+
+    fn queue_future_tx(&mut self, tx: L2Transaction, account_nonce: u32) -> Result<()> {
+        let raw_tx = tx.raw();
+        let sender_id: u32 = raw_tx.from_id().unpack();
+        let nonce: u32 = raw_tx.nonce().unpack();
+        let max_queued_txs = self.config.max_queued_txs_per_sender as usize;
+        // the nonce of a queued tx must be less than `account nonce + max queued txs`
+        if (nonce - account_nonce) as usize >= max_queued_txs {
+            return Err(anyhow!(
+                "nonce is too far in the future, account nonce {}, actual {}",
+                account_nonce,
+                nonce
+            ));
+        }
+        let queued_txs = self.future_queue.entry(sender_id).or_default();
+        if let Some(queued_tx) = queued_txs.get(&nonce) {
+            let fee = get_tx_fee(&tx);
+            let queued_fee = get_tx_fee(queued_tx);
+            if fee <= queued_fee {
+                return Err(anyhow!(
+                    "a tx with nonce {} is already queued, replacement fee {} must be higher than {}",
+                    nonce,
+                    fee,
+                    queued_fee
+                ));
+            }
+        } else if queued_txs.len() >= max_queued_txs {
+            return Err(anyhow!(
+                "too many queued txs of account {}, max {}",
+                sender_id,
+                max_queued_txs
+            ));
+        }
+        self.generator
+            .verify_transaction_signature(&self.state, &tx)?;
+        queued_txs.insert(nonce, tx);
+        Ok(())
+    }
+
+    /// Replace a pending tx which has the same sender and nonce,
+    /// txs after the replaced one are re-executed.
+    fn replace_pending_tx(&mut self, tx: L2Transaction, account_nonce: u32) -> Result<RunResult> {
+        let raw_tx = tx.raw();
+        let sender_id: u32 = raw_tx.from_id().unpack();
+        let nonce: u32 = raw_tx.nonce().unpack();
+        let index = self
+            .queue
+            .iter()
+            .position(|pending_tx| {
+                let pending_nonce: u32 = pending_tx.tx.raw().nonce().unpack();
+                pending_tx.sender_id == sender_id && pending_nonce == nonce
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "invalid nonce, expected {}, actual {}",
+                    account_nonce,
+                    nonce
+                )
+            })?;
+        let fee = get_tx_fee(&tx);
+        let replaced_fee = self.queue[index].fee;
+        if fee <= replaced_fee {
+            return Err(anyhow!(
+                "replacement tx fee {} must be higher than {}",
+                fee,
+                replaced_fee
+            ));
+        }
+        self.generator
+            .verify_transaction_signature(&self.state, &tx)?;
+
+        // re-execute txs from the base state
+        let mut pending_txs = std::mem::take(&mut self.queue);
+        let following_txs = pending_txs.split_off(index + 1);
+        let replaced_tx = pending_txs.pop().expect("replaced tx");
+        self.reset_state();
+        self.recompute_pending_txs(pending_txs);
+        let result = self.push_pending_tx(tx);
+        if result.is_err() {
+            // keep the original tx if the replacement is invalid
+            self.recompute_pending_txs(vec![replaced_tx]);
+        }
+        self.recompute_pending_txs(following_txs);
+        self.promote_future_txs(sender_id);
+        result
+    }
+
+    /// Promote queued txs of the sender which nonce matches the account nonce
+    fn promote_future_txs(&mut self, sender_id: u32) {
+        loop {
+            let account_nonce = match self.state.get_nonce(sender_id) {
+                Ok(nonce) => nonce,
+                Err(err) => {
+                    eprintln!("get nonce of account {} error: {:?}", sender_id, err);
+                    return;
+                }
+            };
+            let queued_txs = match self.future_queue.get_mut(&sender_id) {
+                Some(queued_txs) => queued_txs,
+                None => return,
+            };
+            // remove stale txs
+            *queued_txs = queued_txs.split_off(&account_nonce);
+            let tx = queued_txs.remove(&account_nonce);
+            if queued_txs.is_empty() {
+                self.future_queue.remove(&sender_id);
+            }
+            let tx = match tx {
+                Some(tx) => tx,
+                None => return,
+            };
+            let tx_hash = tx.hash();
+            if let Err(err) = self.push_executable_tx(tx) {
+                eprintln!("drop queued tx {}: {}", tx_hash.pack(), err);
+                return;
+            }
+        }
+    }
+
+    fn promote_all_future_txs(&mut self) {
+        let sender_ids: Vec<u32> = self.future_queue.keys().copied().collect();
+        for sender_id in sender_ids {
+            self.promote_future_txs(sender_id);
+        }
+    }
+
+    /// Push a tx which nonce equals to the account nonce
+    ///
+    /// If the pool is full, the tx replaces the lowest fee tx of other senders,
+    /// or is rejected if it doesn't pay a higher fee.
+    fn push_executable_tx(&mut self, tx: L2Transaction) -> Result<RunResult> {
+        let sender_id: u32 = tx.raw().from_id().unpack();
+        let sender_txs = self
+            .queue
+            .iter()
+            .filter(|pending_tx| pending_tx.sender_id == sender_id)
+            .count();
+        if sender_txs >= self.config.max_txs_per_sender as usize {
+            return Err(anyhow!(
+                "too many pending txs of account {}, max {}",
+                sender_id,
+                self.config.max_txs_per_sender
+            ));
+        }
+        if self.queue.len() < self.config.max_pool_txs as usize {
+            return self.push_pending_tx(tx);
+        }
+        let fee = get_tx_fee(&tx);
+        let evict_index = self
+            .find_lowest_fee_tx(sender_id)
+            .filter(|&index| self.queue[index].fee < fee)
+            .ok_or_else(|| anyhow!("tx pool is full, tx fee {} is too low", fee))?;
+        // make sure the tx is valid before evicting others
+        self.execute(tx.clone())?;
+        let evicted = self.queue.remove(evict_index);
+        eprintln!(
+            "tx pool is full, evict tx {} fee {}",
+            evicted.tx.hash().pack(),
+            evicted.fee
+        );
+        // recompute pending state without the evicted tx
+        let pending_txs = std::mem::take(&mut self.queue);
+        self.reset_state();
+        self.recompute_pending_txs(pending_txs);
+        self.push_pending_tx(tx)
+    }
+
+    fn push_pending_tx(&mut self, tx: L2Transaction) -> Result<RunResult> {
         // 1. execute tx
         let run_result = self.execute(tx.clone())?;
         // 2. update state
         self.state.apply_run_result(&run_result)?;
         // 3. push tx to pool
-        let tx_witness_hash = tx.witness_hash().into();
-        let compacted_post_account_root = self.state.calculate_compacted_account_root()?;
-        let receipt = TxReceipt {
-            tx_witness_hash,
-            compacted_post_account_root,
-            read_data_hashes: run_result.read_data.iter().map(|(hash, _)| *hash).collect(),
-        };
-        self.queue.push((tx, receipt));
+        let sender_id: u32 = tx.raw().from_id().unpack();
+        let fee = get_tx_fee(&tx);
+        self.queue.push(PendingTx { tx, sender_id, fee });
         Ok(run_result)
     }
 
+    /// Find the lowest fee tx which is the last pending tx of its sender,
+    /// evicting it doesn't invalidate other pending txs of the sender.
+    fn find_lowest_fee_tx(&self, exclude_sender_id: u32) -> Option<usize> {
+        let mut last_txs: HashMap<u32, usize> = HashMap::new();
+        for (index, pending_tx) in self.queue.iter().enumerate() {
+            last_txs.insert(pending_tx.sender_id, index);
+        }
+        last_txs
+            .into_iter()
+            .filter(|(sender_id, _index)| *sender_id != exclude_sender_id)
+            .map(|(_sender_id, index)| index)
+            .min_by_key(|&index| (self.queue[index].fee, Reverse(index)))
+    }
+
     /// Execute tx without push it into pool
     pub fn execute(&self, tx: L2Transaction) -> Result<RunResult> {
+        self.execute_on(&self.state, &tx)
+    }
+
+    fn execute_on(&self, state: &OverlayStore<S>, tx: &L2Transaction) -> Result<RunResult> {
         // 1. verify tx signature
-        self.verify_tx(&tx)?;
+        verify_tx(&self.generator, state, tx)?;
         // 2. execute contract
         let raw_tx = tx.raw();
         let run_result = self
             .generator
-            .execute(&self.state, &self.next_block_info, &raw_tx)?;
-        let write_data_bytes: usize = run_result.write_data.values().map(|data| data.len()).sum();
-        if write_data_bytes > MAX_DATA_BYTES_LIMIT {
+            .execute(state, &self.next_block_info, &raw_tx)?;
+        self.check_data_limits(&run_result)?;
+        Ok(run_result)
+    }
+
+    /// Execute tx with the speculative result of the parallel execution
+    fn execute_speculated(
+        &self,
+        execution: &mut ParallelExecution,
+        state: &OverlayStore<S>,
+        index: usize,
+        tx: &L2Transaction,
+    ) -> Result<RunResult> {
+        verify_tx(&self.generator, state, tx)?;
+        let run_result = execution.take_result(
+            &self.generator,
+            state,
+            &self.next_block_info,
+            index,
+            &tx.raw(),
+        )?;
+        self.check_data_limits(&run_result)?;
+        Ok(run_result)
+    }
+
+    fn check_data_limits(&self, run_result: &RunResult) -> Result<()> {
+        let max_data_bytes = self.config.max_tx_data_bytes as usize;
+        let (read_data_bytes, write_data_bytes) = get_data_bytes(&run_result);
+        if write_data_bytes > max_data_bytes {
             return Err(anyhow!(
                 "tx write data exceeded the limitation. write data bytes: {} max data bytes: {}",
                 write_data_bytes,
-                MAX_DATA_BYTES_LIMIT
+                max_data_bytes
             ));
         }
-        let read_data_bytes: usize = run_result.read_data.values().sum();
-        if read_data_bytes > MAX_DATA_BYTES_LIMIT {
+        if read_data_bytes > max_data_bytes {
             return Err(anyhow!(
                 "tx read data exceeded the limitation. read data bytes: {} max data bytes: {}",
                 read_data_bytes,
-                MAX_DATA_BYTES_LIMIT
+                max_data_bytes
             ));
         }
-        Ok(run_result)
+        Ok(())
     }
 
     /// Push a withdrawal request into pool
+    ///
+    /// Withdrawal requests are applied before txs in a block, so pending txs are re-executed
+    /// on top of the withdrawal request.
     pub fn push_withdrawal_request(&mut self, withdrawal_request: WithdrawalRequest) -> Result<()> {
         self.verify_withdrawal_request(&withdrawal_request)?;
-        self.withdrawal_queue.push(withdrawal_request);
+        self.withdrawal_queue.push(withdrawal_request.clone());
+        let pending_txs = std::mem::take(&mut self.queue);
+        self.reset_state();
+        self.recompute_pending_txs(pending_txs);
+        self.append_journal(JournalEntry::WithdrawalRequest(withdrawal_request));
         Ok(())
     }
 
-    pub fn verify_withdrawal_request(&self, withdrawal_request: &WithdrawalRequest) -> Result<()> {
-        self.generator
-            .verify_withdrawal_request(&self.state, withdrawal_request)
-            .map_err(Into::into)
+    /// Reset the state to the base state with queued withdrawal requests applied,
+    /// withdrawal requests which are invalid on the base state are dropped
+    fn reset_state(&mut self) {
+        let mut state = self.base_state.clone();
+        let generator = &self.generator;
+        self.withdrawal_queue.retain(|withdrawal_request| {
+            let result = generator
+                .verify_withdrawal_request(&state, withdrawal_request)
+                .and_then(|_| {
+                    state.apply_withdrawal_requests(std::slice::from_ref(withdrawal_request))
+                });
+            if let Err(err) = &result {
+                eprintln!(
+                    "drop withdrawal request {}: {}",
+                    withdrawal_request.raw().hash().pack(),
+                    err
+                );
+            }
+            result.is_ok()
+        });
+        self.state = state;
     }
 
-    fn verify_tx(&self, tx: &L2Transaction) -> Result<()> {
-        let raw_tx = tx.raw();
-        let sender_id: u32 = raw_tx.from_id().unpack();
+    fn append_journal(&mut self, entry: JournalEntry) {
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(err) = journal.append(&entry) {
+                eprintln!("append tx pool journal error: {}", err);
+            }
+        }
+    }
 
-        // verify nonce
-        let account_nonce: u32 = self.state.get_nonce(sender_id).expect("get nonce");
-        let nonce: u32 = raw_tx.nonce().unpack();
-        if nonce != account_nonce {
+    /// Rewrite the journal with entries in the pool, removed entries are dropped from the journal
+    fn compact_journal(&mut self) {
+        let journal = match self.journal.as_mut() {
+            Some(journal) => journal,
+            None => return,
+        };
+        let pending_txs = self
+            .queue
+            .iter()
+            .map(|pending_tx| JournalEntry::Transaction(pending_tx.tx.clone()));
+        let queued_txs = self
+            .future_queue
+            .values()
+            .flat_map(|txs| txs.values())
+            .map(|tx| JournalEntry::Transaction(tx.clone()));
+        let withdrawal_requests = self
+            .withdrawal_queue
+            .iter()
+            .map(|request| JournalEntry::WithdrawalRequest(request.clone()));
+        // withdrawal requests are replayed first, the nonces of pending txs follow them
+        let entries: Vec<_> = withdrawal_requests
+            .chain(pending_txs)
+            .chain(queued_txs)
+            .collect();
+        if let Err(err) = journal.rewrite(&entries) {
+            eprintln!("rewrite tx pool journal error: {}", err);
+        }
+    }
+
+    /// Verify withdrawal request
+    ///
+    /// Withdrawal requests are applied before txs in a block, so the request is verified on the tip state
+    /// with queued withdrawal requests applied, the nonce of the request follows the queued ones.
+    ///
+    /// The request is rejected if the account has pending txs, since they have taken the nonce.
+    pub fn verify_withdrawal_request(&self, withdrawal_request: &WithdrawalRequest) -> Result<()> {
+        let account_script_hash: [u8; 32] = withdrawal_request.raw().account_script_hash().unpack();
+        let account_id = self
+            .base_state
+            .get_account_id_by_script_hash(&account_script_hash.into())?
+            .ok_or_else(|| anyhow!("unknown withdrawal account"))?;
+        if self
+            .queue
+            .iter()
+            .any(|pending_tx| pending_tx.sender_id == account_id)
+        {
             return Err(anyhow!(
-                "invalid nonce, expected {}, actual {}",
-                account_nonce,
-                nonce
+                "account {} has pending txs, withdraw after they are packaged",
+                account_id
             ));
         }
-
-        // verify signature
-        let script_hash = self
-            .state
-            .get_script_hash(sender_id)
-            .expect("get script hash");
-        let script = self.state.get_script(&script_hash).expect("get script");
-        let pubkey_hash = {
-            let mut buf = [0u8; 20];
-            let args: Vec<u8> = script.args().unpack();
-            // pubkey hash length is 20
-            assert_eq!(args.len(), 20);
-            buf.copy_from_slice(args.as_slice());
-            buf.into()
-        };
-        let tx_hash = tx.hash();
-        let sig = Signature(tx.signature().unpack());
-        verify_signature(&sig, &tx_hash, &pubkey_hash)?;
+        let mut state = self.base_state.clone();
+        state.apply_withdrawal_requests(&self.withdrawal_queue)?;
+        self.generator
+            .verify_withdrawal_request(&state, withdrawal_request)?;
+        // reject the request if the custodian cells can't pay it even the queue is empty
+        if let Some(mut liquidity) = self.query_custodian_liquidity()? {
+            liquidity.reserve(
+                withdrawal_request,
+                self.config.min_custodian_change_capacity,
+            )?;
+        }
         Ok(())
     }
 
+    /// Returns None if the custodian provider is not set
+    fn query_custodian_liquidity(&self) -> Result<Option<CustodianLiquidity>> {
+        self.custodian_provider
+            .as_ref()
+            .map(|provider| provider.query_liquidity())
+            .transpose()
+    }
+
     /// Package
     /// this method return a tx pool package contains txs and withdrawal requests,
     /// and remove these from the pool
+    ///
+    /// Txs are packaged in the order of fee, txs of the same sender are still in the order of nonce,
+    /// packaged txs are re-executed on the base state to generate receipts in the new order.
+    ///
+    /// Txs exceeding the block data budget or the block cycles budget are left in the pool for the next block.
+    ///
+    /// Selected txs are executed in parallel, conflicting txs are re-executed in order.
     pub fn package(&mut self, deposition_requests: &[DepositionRequest]) -> Result<TxPoolPackage> {
-        let txs_limit = min(MAX_PACKAGED_TXS, self.queue.len());
-        let tx_receipts = self.queue.drain(..txs_limit).collect();
-        // reset overlay, we need to record deposition / withdrawal touched keys to generate proof for state
-        self.state.overlay_store_mut().clear_touched_keys();
-        // fetch withdrawal request and rerun verifier, drop invalid requests
-        let withdrawal_limit = min(MAX_PACKAGED_WITHDRAWAL, self.withdrawal_queue.len());
-        let withdrawal_requests: Vec<_> = self
-            .withdrawal_queue
-            .drain(..withdrawal_limit)
-            .collect::<Vec<_>>()
+        let mut pending_txs: Vec<Option<PendingTx>> = std::mem::take(&mut self.queue)
             .into_iter()
-            .filter(|withdrawal_request| self.verify_withdrawal_request(withdrawal_request).is_ok())
+            .map(Some)
             .collect();
-        // TODO make sure the remain capacity is enough to pay custodian cell
-        // apply withdrawal request to the state
-        self.state.apply_withdrawal_requests(&withdrawal_requests)?;
+        let mut state = self.base_state.clone();
+        // reset overlay, we need to record deposition / withdrawal touched keys to generate proof for state
+        state.overlay_store_mut().clear_touched_keys();
+        // withdrawals are paid by the custodian cells, no withdrawal is packaged if the liquidity query is failed,
+        // the liquidity is not checked if the custodian provider is not set
+        let (withdrawal_limit, mut custodian_liquidity) = if self.withdrawal_queue.is_empty() {
+            (0, None)
+        } else {
+            match self.query_custodian_liquidity() {
+                Ok(liquidity) => (
+                    min(
+                        self.config.max_packaged_withdrawals as usize,
+                        self.withdrawal_queue.len(),
+                    ),
+                    liquidity,
+                ),
+                Err(err) => {
+                    eprintln!("query custodian liquidity error: {}", err);
+                    (0, None)
+                }
+            }
+        };
+        // fetch withdrawal requests and rerun verifier, drop invalid requests.
+        // requests are applied one by one, so requests of an account with consecutive nonces are accepted
+        let mut withdrawal_requests = Vec::with_capacity(withdrawal_limit);
+        // requests which can't be paid by the custodian cells, left in the queue
+        let mut unpaid_withdrawal_requests = Vec::new();
+        // once a request is unpaid, the following requests of the account are left in the queue to keep the nonce order
+        let mut unpaid_accounts = HashSet::new();
+        for withdrawal_request in self.withdrawal_queue.drain(..withdrawal_limit) {
+            let account_script_hash: [u8; 32] =
+                withdrawal_request.raw().account_script_hash().unpack();
+            if unpaid_accounts.contains(&account_script_hash) {
+                unpaid_withdrawal_requests.push(withdrawal_request);
+                continue;
+            }
+            if let Err(err) = self
+                .generator
+                .verify_withdrawal_request(&state, &withdrawal_request)
+            {
+                eprintln!(
+                    "drop withdrawal request {}: {}",
+                    withdrawal_request.raw().hash().pack(),
+                    err
+                );
+                continue;
+            }
+            let reserved = custodian_liquidity.as_mut().map(|liquidity| {
+                liquidity.reserve(
+                    &withdrawal_request,
+                    self.config.min_custodian_change_capacity,
+                )
+            });
+            if let Some(Err(err)) = reserved {
+                eprintln!(
+                    "withdrawal request {} is left in the pool: {}",
+                    withdrawal_request.raw().hash().pack(),
+                    err
+                );
+                unpaid_accounts.insert(account_script_hash);
+                unpaid_withdrawal_requests.push(withdrawal_request);
+                continue;
+            }
+            state.apply_withdrawal_requests(std::slice::from_ref(&withdrawal_request))?;
+            withdrawal_requests.push(withdrawal_request);
+        }
+        unpaid_withdrawal_requests.extend(self.withdrawal_queue.drain(..));
+        self.withdrawal_queue = unpaid_withdrawal_requests;
         // apply deposition request to the state
-        self.state.apply_deposition_requests(&deposition_requests)?;
-        let post_account_state = get_account_state(&self.state)?;
-        let touched_keys = self
-            .state
+        state.apply_deposition_requests(&deposition_requests)?;
+        let touched_keys = state
             .overlay_store_mut()
             .touched_keys()
             .into_iter()
             .map(|k| (*k).into())
             .collect();
+        // withdrawals and depositions are applied before txs, as the generator does
+        let mut tx_receipts = Vec::new();
+        let max_block_data_bytes = self.config.max_block_data_bytes as usize;
+        let (mut block_read_data_bytes, mut block_write_data_bytes) = (0usize, 0usize);
+        let max_block_cycles = self.config.max_block_cycles;
+        let mut block_cycles = 0u64;
+        // senders which txs are skipped, the following txs of them can't be packaged
+        let mut skipped_senders = HashSet::new();
+        let max_packaged_txs = self.config.max_packaged_txs as usize;
+        let selected_txs: Vec<(usize, PendingTx)> =
+            select_txs_by_fee(&pending_txs, max_packaged_txs)
+                .into_iter()
+                .filter_map(|index| pending_txs[index].take().map(|tx| (index, tx)))
+                .collect();
+        let raw_txs: Vec<_> = selected_txs
+            .iter()
+            .map(|(_index, pending_tx)| pending_tx.tx.raw())
+            .collect();
+        let mut execution =
+            self.generator
+                .execute_parallel(&state, &self.next_block_info, &raw_txs);
+        for (position, (index, pending_tx)) in selected_txs.into_iter().enumerate() {
+            let sender_id = pending_tx.sender_id;
+            if skipped_senders.contains(&sender_id) {
+                pending_txs[index] = Some(pending_tx);
+                continue;
+            }
+            let tx = pending_tx.tx.clone();
+            let run_result = match self.execute_speculated(&mut execution, &state, position, &tx) {
+                Ok(run_result) => run_result,
+                Err(err) => {
+                    eprintln!("skip packaging tx {}: {}", tx.hash().pack(), err);
+                    skipped_senders.insert(sender_id);
+                    pending_txs[index] = Some(pending_tx);
+                    continue;
+                }
+            };
+            let (read_data_bytes, write_data_bytes) = get_data_bytes(&run_result);
+            if block_read_data_bytes + read_data_bytes > max_block_data_bytes
+                || block_write_data_bytes + write_data_bytes > max_block_data_bytes
+                || block_cycles.saturating_add(run_result.used_cycles) > max_block_cycles
+            {
+                skipped_senders.insert(sender_id);
+                pending_txs[index] = Some(pending_tx);
+                continue;
+            }
+            block_read_data_bytes += read_data_bytes;
+            block_write_data_bytes += write_data_bytes;
+            block_cycles += run_result.used_cycles;
+            state.apply_run_result(&run_result)?;
+            execution.commit(&run_result);
+            let receipt = build_receipt(&state, &tx, &run_result)?;
+            tx_receipts.push((tx, receipt));
+        }
+        let post_account_state = get_account_state(&state)?;
+        // execute remain txs on top of the packaged state
+        self.base_state = state;
+        self.reset_state();
+        self.recompute_pending_txs(pending_txs.into_iter().flatten().collect());
+        self.promote_all_future_txs();
+        self.compact_journal();
         let pkg = TxPoolPackage {
             touched_keys,
             tx_receipts,
@@ -191,36 +674,185 @@ impl<S: Store<SMTH256>> TxPool<S> {
     }
 
     /// Update tip and state
-    /// this method reset tip and tx_pool states
+    /// this method reset tip and tx_pool states, pending txs are re-executed on the new state
     pub fn update_tip(
         &mut self,
         tip: &L2Block,
         state: OverlayStore<S>,
         nb_ctx: NextBlockContext,
     ) -> Result<()> {
-        // TODO catch abandoned txs and recompute them.
-        self.state = state;
+        let included_txs: HashSet<[u8; 32]> =
+            tip.transactions().into_iter().map(|tx| tx.hash()).collect();
+        let pending_txs = std::mem::take(&mut self.queue)
+            .into_iter()
+            .filter(|pending_tx| !included_txs.contains(&pending_tx.tx.hash()))
+            .collect();
+        self.base_state = state;
+        self.reset_state();
         self.update_tip_without_status(tip, nb_ctx)?;
+        self.recompute_pending_txs(pending_txs);
+        self.promote_all_future_txs();
+        self.compact_journal();
         Ok(())
     }
 
+    /// Re-execute pending txs against the current state, invalid txs are dropped.
+    fn recompute_pending_txs(&mut self, pending_txs: Vec<PendingTx>) {
+        for pending_tx in pending_txs {
+            let tx_hash = pending_tx.tx.hash();
+            if let Err(err) = self.push_pending_tx(pending_tx.tx) {
+                eprintln!("drop pending tx {}: {}", tx_hash.pack(), err);
+            }
+        }
+    }
+
     /// Update tip
     /// this method reset tip and generate a new checkpoint for current state
     ///
-    /// Notice this fucntion may cause inconsistency between tip and status
+    /// Notice this fucntion may cause inconsistency between tip and status,
+    /// pending txs are dropped since they can't be recomputed without a new state,
+    /// use `update_tip` to keep them.
     pub fn update_tip_without_status(
         &mut self,
         tip: &L2Block,
         nb_ctx: NextBlockContext,
     ) -> Result<()> {
-        // TODO catch abandoned txs and recompute them.
         self.queue.clear();
         self.next_block_info = gen_next_block_info(tip, nb_ctx)?;
-        self.next_prev_account_state = get_account_state(&self.state)?;
+        // the state of the tip, queued withdrawal requests are not applied
+        self.next_prev_account_state = get_account_state(&self.base_state)?;
         Ok(())
     }
 }
 
+fn verify_tx<S: State + CodeStore>(
+    generator: &Generator,
+    state: &S,
+    tx: &L2Transaction,
+) -> Result<()> {
+    let raw_tx = tx.raw();
+    let sender_id: u32 = raw_tx.from_id().unpack();
+
+    // verify nonce
+    let account_nonce: u32 = state.get_nonce(sender_id)?;
+    let nonce: u32 = raw_tx.nonce().unpack();
+    if nonce != account_nonce {
+        return Err(anyhow!(
+            "invalid nonce, expected {}, actual {}",
+            account_nonce,
+            nonce
+        ));
+    }
+
+    generator
+        .verify_transaction_signature(state, tx)
+        .map_err(Into::into)
+}
+
+/// Fee of the tx, only the `SUDTTransfer` tx pays fee for now
+fn get_tx_fee(tx: &L2Transaction) -> u128 {
+    let args: Vec<u8> = tx.raw().args().unpack();
+    match SUDTArgs::from_slice(&args).map(|args| args.to_enum()) {
+        Ok(SUDTArgsUnion::SUDTTransfer(transfer)) => transfer.fee().unpack(),
+        _ => 0,
+    }
+}
+
+/// Select txs in the order of fee, returns indexes of txs
+///
+/// A tx is selectable only if all the prior txs of the same sender are selected,
+/// txs paying the same fee are selected in the order of arrival.
+fn select_txs_by_fee(pending_txs: &[Option<PendingTx>], limit: usize) -> Vec<usize> {
+    let mut sender_txs: HashMap<u32, VecDeque<usize>> = HashMap::new();
+    for (index, pending_tx) in pending_txs.iter().enumerate() {
+        if let Some(pending_tx) = pending_tx {
+            sender_txs
+                .entry(pending_tx.sender_id)
+                .or_default()
+                .push_back(index);
+        }
+    }
+    let fee_of = |index: usize| pending_txs[index].as_ref().map(|tx| tx.fee).unwrap_or(0);
+    let mut heap = BinaryHeap::new();
+    for (sender_id, indexes) in sender_txs.iter_mut() {
+        if let Some(index) = indexes.pop_front() {
+            heap.push((fee_of(index), Reverse(index), *sender_id));
+        }
+    }
+    let mut selected = Vec::with_capacity(min(limit, pending_txs.len()));
+    while selected.len() < limit {
+        let (_fee, Reverse(index), sender_id) = match heap.pop() {
+            Some(item) => item,
+            None => break,
+        };
+        selected.push(index);
+        if let Some(next_index) = sender_txs
+            .get_mut(&sender_id)
+            .and_then(|txs| txs.pop_front())
+        {
+            heap.push((fee_of(next_index), Reverse(next_index), sender_id));
+        }
+    }
+    selected
+}
+
+/// Returns read data bytes and write data bytes of the tx
+fn get_data_bytes(run_result: &RunResult) -> (usize, usize) {
+    let read_data_bytes: usize = run_result.read_data.values().sum();
+    let write_data_bytes: usize = run_result.write_data.values().map(|data| data.len()).sum();
+    (read_data_bytes, write_data_bytes)
+}
+
+fn verify_config(config: &TxPoolConfig, generator_config: &GeneratorConfig) -> Result<()> {
+    if config.max_packaged_txs == 0 || config.max_pool_txs < config.max_packaged_txs {
+        return Err(anyhow!(
+            "invalid tx pool config, max_pool_txs {} must be greater than or equal to max_packaged_txs {}, and max_packaged_txs must be greater than 0",
+            config.max_pool_txs,
+            config.max_packaged_txs
+        ));
+    }
+    if config.max_txs_per_sender == 0 {
+        return Err(anyhow!(
+            "invalid tx pool config, max_txs_per_sender must be greater than 0"
+        ));
+    }
+    if config.max_tx_data_bytes == 0 || config.max_block_data_bytes < config.max_tx_data_bytes {
+        return Err(anyhow!(
+            "invalid tx pool config, max_block_data_bytes {} must be greater than or equal to max_tx_data_bytes {}, and max_tx_data_bytes must be greater than 0",
+            config.max_block_data_bytes,
+            config.max_tx_data_bytes
+        ));
+    }
+    if config.max_block_cycles < generator_config.max_cycles {
+        return Err(anyhow!(
+            "invalid tx pool config, max_block_cycles {} must be greater than or equal to max cycles of a tx {}",
+            config.max_block_cycles,
+            generator_config.max_cycles
+        ));
+    }
+    if generator_config.execution_threads == 0 {
+        return Err(anyhow!(
+            "invalid generator config, execution_threads must be greater than 0"
+        ));
+    }
+    Ok(())
+}
+
+fn build_receipt<S: State>(
+    state: &S,
+    tx: &L2Transaction,
+    run_result: &RunResult,
+) -> Result<TxReceipt> {
+    let tx_witness_hash = tx.witness_hash().into();
+    let compacted_post_account_root = state.calculate_compacted_account_root()?;
+    Ok(TxReceipt {
+        tx_witness_hash,
+        compacted_post_account_root,
+        read_data_hashes: run_result.read_data.iter().map(|(hash, _)| *hash).collect(),
+        used_cycles: run_result.used_cycles,
+    })
+}
+
 fn get_account_state<S: State>(state: &S) -> Result<MerkleState> {
     let root = state.calculate_root()?;
     let count = state.get_account_count()?;
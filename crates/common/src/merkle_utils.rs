@@ -2,7 +2,7 @@ use crate::vec::Vec;
 use crate::{
     blake2b::new_blake2b,
     h256_ext::H256Ext,
-    smt::{default_store::DefaultStore, Error, H256, SMT},
+    smt::{default_store::DefaultStore, CompiledMerkleProof, Error, H256, SMT},
 };
 
 // Calculate compacted account root
@@ -26,3 +26,21 @@ pub fn calculate_merkle_root(leaves: Vec<[u8; 32]>) -> Result<[u8; 32], Error> {
     }
     Ok((*tree.root()).into())
 }
+
+/// Compute merkle proof of a leaf from vectors
+pub fn calculate_merkle_proof(
+    leaves: Vec<[u8; 32]>,
+    index: u32,
+) -> Result<CompiledMerkleProof, Error> {
+    let mut tree = SMT::<DefaultStore<H256>>::default();
+    for (i, leaf) in leaves.into_iter().enumerate() {
+        tree.update(H256::from_u32(i as u32), leaf.into())?;
+    }
+    let key = H256::from_u32(index);
+    let leaf = tree.get(&key)?;
+    let mut keys = Vec::with_capacity(1);
+    keys.push(key);
+    let mut leaves = Vec::with_capacity(1);
+    leaves.push((key, leaf));
+    tree.merkle_proof(keys)?.compile(leaves)
+}
@@ -1,4 +1,5 @@
 use ckb_types::packed::Script;
+use std::path::PathBuf;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Config {
@@ -7,6 +8,10 @@ pub struct Config {
     pub rpc: RPC,
     pub genesis: GenesisConfig,
     pub aggregator: Option<AggregatorConfig>,
+    pub tx_pool: TxPoolConfig,
+    pub generator: GeneratorConfig,
+    /// backends of layer2 contracts, the meta contract and sUDT backends must be included
+    pub backends: Vec<BackendConfig>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -16,7 +21,12 @@ pub struct AggregatorConfig {
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct SignerConfig {}
+pub struct SignerConfig {
+    /// keystore file of the aggregator's private key, raw hex or encrypted JSON
+    pub keystore_path: PathBuf,
+    /// password of the encrypted JSON keystore
+    pub password: Option<String>,
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ConsensusConfig {
@@ -31,9 +41,127 @@ pub struct GenesisConfig {
 #[derive(Clone, Debug, PartialEq)]
 pub struct ChainConfig {
     pub rollup_type_script: Script,
+    /// lock of the deposition cells, only `code_hash` and `hash_type` are matched
+    pub deposition_lock: Script,
+    /// type of the layer1 Simple UDT cells, only `code_hash` and `hash_type` are matched
+    pub l1_sudt_type: Script,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct RPC {
     pub listen: String,
 }
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxPoolConfig {
+    /// max packaged txs in a l2block
+    pub max_packaged_txs: u32,
+    /// max packaged withdrawal requests in a l2block
+    pub max_packaged_withdrawals: u32,
+    /// max pending txs in the pool, the lowest fee txs are evicted when the pool is full
+    pub max_pool_txs: u32,
+    /// max pending txs of a sender
+    pub max_txs_per_sender: u32,
+    /// max future nonce txs queued for a sender
+    pub max_queued_txs_per_sender: u32,
+    /// max read data bytes and max write data bytes of a tx
+    pub max_tx_data_bytes: u32,
+    /// max cumulative read data bytes and write data bytes of txs in a l2block
+    pub max_block_data_bytes: u32,
+    /// max cumulative cycles of txs in a l2block
+    pub max_block_cycles: u64,
+    /// min capacity of a custodian change cell, in shannons,
+    /// custodian cells must keep enough capacity to create change cells after paying withdrawals
+    pub min_custodian_change_capacity: u64,
+    /// journal file of pending txs and withdrawal requests, the pool is not persisted if it's not set
+    pub journal_path: Option<PathBuf>,
+}
+
+impl Default for TxPoolConfig {
+    fn default() -> Self {
+        TxPoolConfig {
+            max_packaged_txs: 6000,
+            max_packaged_withdrawals: 10,
+            max_pool_txs: 12000,
+            max_txs_per_sender: 64,
+            max_queued_txs_per_sender: 16,
+            max_tx_data_bytes: 25_000,
+            max_block_data_bytes: 1_000_000,
+            max_block_cycles: 3_500_000_000,
+            min_custodian_change_capacity: 300_0000_0000,
+            journal_path: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeneratorConfig {
+    /// min capacity of a withdrawal request, in shannons
+    pub min_withdrawal_capacity: u64,
+    /// threads to execute txs in parallel, txs are executed sequentially if it's 1
+    pub execution_threads: u32,
+    /// max cycles of a tx, the execution is aborted once the limit is exceeded
+    pub max_cycles: u64,
+    /// lock algorithms of account lock scripts, indexed by the lock code hash,
+    /// registered over the default secp256k1 lock under `ACCOUNT_LOCK_CODE_HASH`
+    pub account_locks: Vec<AccountLockConfig>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            min_withdrawal_capacity: 100_0000_0000,
+            execution_threads: 4,
+            max_cycles: 70_000_000,
+            account_locks: vec![AccountLockConfig {
+                code_hash: ALWAYS_SUCCESS_LOCK_CODE_HASH,
+                lock_algorithm: LockAlgorithmType::AlwaysSuccess,
+            }],
+        }
+    }
+}
+
+/// Code hash of the always success account lock registered by default
+pub const ALWAYS_SUCCESS_LOCK_CODE_HASH: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockAlgorithmType {
+    /// accepts any signature
+    AlwaysSuccess,
+    /// lock_args is the blake160 of the secp256k1 pubkey
+    Secp256k1,
+    /// lock_args is the Ethereum address, signed by `personal_sign`
+    EthSecp256k1,
+    /// M of N multisig, only 1 of N locks can be unlocked by the 65 bytes signature of txs
+    Multisig,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountLockConfig {
+    /// code hash of the account lock script
+    pub code_hash: [u8; 32],
+    pub lock_algorithm: LockAlgorithmType,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackendConfig {
+    /// validator binary, the backend is registered under its code hash
+    pub validator_path: PathBuf,
+    /// generator binary executed by layer2 txs sent to the backend
+    pub generator_path: PathBuf,
+    /// expected code hash of the validator, the backend is rejected if the loaded binary doesn't match
+    pub validator_code_hash: Option<[u8; 32]>,
+    pub backend_type: BackendType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendType {
+    /// the meta contract which creates contract accounts
+    Meta,
+    /// the simple UDT contract
+    Sudt,
+    /// other layer2 contracts
+    Unknown,
+}
@@ -0,0 +1,68 @@
+use gw_common::H256;
+use gw_types::bytes::Bytes;
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::error::LockAlgorithmError;
+
+use super::{secp256k1::recover_pubkey, LockAlgorithm};
+
+/// Prefix of the Ethereum `personal_sign` message, the length of the message is always 32
+const PERSONAL_MESSAGE_PREFIX: &[u8] = b"\x19Ethereum Signed Message:\n32";
+
+/// Ethereum compatible secp256k1 lock algorithm
+///
+/// lock_args is the 20 bytes Ethereum address: keccak256(uncompressed pubkey)[12..],
+/// signature is signed by `personal_sign`: `r(32 bytes) | s(32 bytes) | v(1 byte)`,
+/// v is either the recovery id or the recovery id plus 27.
+///
+/// Usage
+/// register EthSecp256k1 to AccountLockManage
+///
+/// manage.register_lock_algorithm(code_hash, Box::new(EthSecp256k1::default()));
+#[derive(Debug, Default)]
+pub struct EthSecp256k1;
+
+impl LockAlgorithm for EthSecp256k1 {
+    fn verify_signature(
+        &self,
+        lock_args: Bytes,
+        signature: Bytes,
+        message: H256,
+    ) -> Result<bool, LockAlgorithmError> {
+        // Ethereum address length is 20
+        if lock_args.len() != 20 {
+            return Err(LockAlgorithmError::InvalidLockArgs);
+        }
+        if signature.len() != 65 {
+            return Err(LockAlgorithmError::InvalidSignature);
+        }
+        let v = signature[64];
+        let recid = if v >= 27 { v - 27 } else { v };
+        let message = personal_message_hash(message.as_slice());
+        let pubkey = match recover_pubkey(&signature[..64], recid, &message)? {
+            Some(pubkey) => pubkey,
+            None => return Ok(false),
+        };
+        // skip the 0x04 prefix of the uncompressed pubkey
+        let pubkey_hash = keccak256(&pubkey.serialize_uncompressed()[1..]);
+        Ok(&pubkey_hash[12..] == lock_args.as_ref())
+    }
+}
+
+/// keccak256(PERSONAL_MESSAGE_PREFIX | message)
+fn personal_message_hash(message: &[u8]) -> [u8; 32] {
+    let mut buf = [0u8; 32];
+    let mut hasher = Keccak::v256();
+    hasher.update(PERSONAL_MESSAGE_PREFIX);
+    hasher.update(message);
+    hasher.finalize(&mut buf);
+    buf
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut buf = [0u8; 32];
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    hasher.finalize(&mut buf);
+    buf
+}
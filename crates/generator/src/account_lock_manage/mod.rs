@@ -1,17 +1,26 @@
 use std::collections::HashMap;
 
-use gw_common::H256;
-use gw_types::{bytes::Bytes, packed::Signature};
+use gw_common::{ACCOUNT_LOCK_CODE_HASH, H256};
+use gw_config::{AccountLockConfig, LockAlgorithmType};
+use gw_types::bytes::Bytes;
 
 pub mod always_success;
+pub mod eth_secp256k1;
+pub mod multisig;
+pub mod secp256k1;
+
+use self::{
+    always_success::AlwaysSuccess, eth_secp256k1::EthSecp256k1, multisig::Multisig,
+    secp256k1::Secp256k1,
+};
 
 use crate::error::LockAlgorithmError;
 
-pub trait LockAlgorithm {
+pub trait LockAlgorithm: Send + Sync {
     fn verify_signature(
         &self,
         lock_args: Bytes,
-        signature: Signature,
+        signature: Bytes,
         message: H256,
     ) -> Result<bool, LockAlgorithmError>;
 }
@@ -20,15 +29,34 @@ pub struct AccountLockManage {
     locks: HashMap<H256, Box<dyn LockAlgorithm>>,
 }
 
+/// The secp256k1 lock algorithm is registered under `ACCOUNT_LOCK_CODE_HASH` by default
 impl Default for AccountLockManage {
     fn default() -> Self {
-        AccountLockManage {
+        let mut manage = AccountLockManage {
             locks: Default::default(),
-        }
+        };
+        manage.register_lock_algorithm(ACCOUNT_LOCK_CODE_HASH.into(), Box::new(Secp256k1));
+        manage
     }
 }
 
 impl AccountLockManage {
+    /// Register the configured lock algorithms over the default ones,
+    /// a configured code hash replaces the default lock algorithm of it
+    pub fn from_config(configs: &[AccountLockConfig]) -> Self {
+        let mut manage = Self::default();
+        for config in configs {
+            let lock_algo: Box<dyn LockAlgorithm> = match config.lock_algorithm {
+                LockAlgorithmType::AlwaysSuccess => Box::new(AlwaysSuccess),
+                LockAlgorithmType::Secp256k1 => Box::new(Secp256k1),
+                LockAlgorithmType::EthSecp256k1 => Box::new(EthSecp256k1),
+                LockAlgorithmType::Multisig => Box::new(Multisig),
+            };
+            manage.register_lock_algorithm(config.code_hash.into(), lock_algo);
+        }
+        manage
+    }
+
     pub fn register_lock_algorithm(&mut self, code_hash: H256, lock_algo: Box<dyn LockAlgorithm>) {
         self.locks.insert(code_hash, lock_algo);
     }
@@ -0,0 +1,77 @@
+use gw_common::H256;
+use gw_types::bytes::Bytes;
+
+use crate::error::LockAlgorithmError;
+
+use super::{
+    secp256k1::{pubkey_hash, recover_pubkey},
+    LockAlgorithm,
+};
+
+const PUBKEY_HASH_SIZE: usize = 20;
+const SIGNATURE_SIZE: usize = 65;
+
+/// M of N multisig lock algorithm
+///
+/// lock_args: `threshold(1 byte) | pubkeys count(1 byte) | blake160(pubkey) * pubkeys count`,
+/// the threshold must be in the range `1..=pubkeys count`.
+///
+/// signature: `recoverable signature(65 bytes) * threshold`,
+/// each signature must be signed by a different pubkey of the lock_args.
+///
+/// Signatures of layer2 txs and withdrawal requests are 65 bytes,
+/// so only locks with threshold 1 can be unlocked by them.
+///
+/// Usage
+/// register Multisig to AccountLockManage
+///
+/// manage.register_lock_algorithm(code_hash, Box::new(Multisig::default()));
+#[derive(Debug, Default)]
+pub struct Multisig;
+
+impl LockAlgorithm for Multisig {
+    fn verify_signature(
+        &self,
+        lock_args: Bytes,
+        signature: Bytes,
+        message: H256,
+    ) -> Result<bool, LockAlgorithmError> {
+        let (threshold, pubkey_hashes) = parse_lock_args(&lock_args)?;
+        if signature.len() != threshold * SIGNATURE_SIZE {
+            return Err(LockAlgorithmError::InvalidSignature);
+        }
+        let mut signed = vec![false; pubkey_hashes.len()];
+        for sig in signature.chunks(SIGNATURE_SIZE) {
+            let pubkey = match recover_pubkey(&sig[..64], sig[64], message.as_slice())? {
+                Some(pubkey) => pubkey,
+                None => return Ok(false),
+            };
+            let hash = pubkey_hash(&pubkey);
+            // a pubkey can only sign once
+            let index =
+                (0..pubkey_hashes.len()).find(|&i| !signed[i] && pubkey_hashes[i] == &hash[..]);
+            match index {
+                Some(i) => signed[i] = true,
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Returns the threshold and pubkey hashes
+fn parse_lock_args(lock_args: &[u8]) -> Result<(usize, Vec<&[u8]>), LockAlgorithmError> {
+    if lock_args.len() < 2 {
+        return Err(LockAlgorithmError::InvalidLockArgs);
+    }
+    let threshold = lock_args[0] as usize;
+    let pubkeys_count = lock_args[1] as usize;
+    if threshold == 0
+        || threshold > pubkeys_count
+        || lock_args.len() != 2 + pubkeys_count * PUBKEY_HASH_SIZE
+    {
+        return Err(LockAlgorithmError::InvalidLockArgs);
+    }
+    let pubkey_hashes = lock_args[2..].chunks(PUBKEY_HASH_SIZE).collect();
+    Ok((threshold, pubkey_hashes))
+}
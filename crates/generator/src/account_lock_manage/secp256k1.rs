@@ -0,0 +1,72 @@
+use gw_common::{blake2b::new_blake2b, H256};
+use gw_types::bytes::Bytes;
+use lazy_static::lazy_static;
+use secp256k1::{
+    recovery::{RecoverableSignature, RecoveryId},
+    PublicKey,
+};
+
+use crate::error::LockAlgorithmError;
+
+use super::LockAlgorithm;
+
+lazy_static! {
+    static ref SECP256K1: secp256k1::Secp256k1<secp256k1::VerifyOnly> =
+        secp256k1::Secp256k1::verification_only();
+}
+
+/// Secp256k1 lock algorithm
+///
+/// lock_args is the blake2b-160 hash of the compressed pubkey,
+/// signature is a recoverable signature: `compact signature(64 bytes) | recovery id(1 byte)`.
+#[derive(Debug, Default)]
+pub struct Secp256k1;
+
+impl LockAlgorithm for Secp256k1 {
+    fn verify_signature(
+        &self,
+        lock_args: Bytes,
+        signature: Bytes,
+        message: H256,
+    ) -> Result<bool, LockAlgorithmError> {
+        // pubkey hash length is 20
+        if lock_args.len() != 20 {
+            return Err(LockAlgorithmError::InvalidLockArgs);
+        }
+        if signature.len() != 65 {
+            return Err(LockAlgorithmError::InvalidSignature);
+        }
+        let pubkey = match recover_pubkey(&signature[..64], signature[64], message.as_slice())? {
+            Some(pubkey) => pubkey,
+            None => return Ok(false),
+        };
+        Ok(&pubkey_hash(&pubkey)[..] == lock_args.as_ref())
+    }
+}
+
+/// blake2b(compressed pubkey)[..20]
+pub(super) fn pubkey_hash(pubkey: &PublicKey) -> [u8; 20] {
+    let mut buf = [0u8; 32];
+    let mut hasher = new_blake2b();
+    hasher.update(&pubkey.serialize());
+    hasher.finalize(&mut buf);
+    let mut pubkey_hash = [0u8; 20];
+    pubkey_hash.copy_from_slice(&buf[..20]);
+    pubkey_hash
+}
+
+/// Recover the pubkey from a compact signature and the recovery id,
+/// returns `None` if the pubkey can't be recovered from the message
+pub(super) fn recover_pubkey(
+    compact_signature: &[u8],
+    recid: u8,
+    message: &[u8],
+) -> Result<Option<PublicKey>, LockAlgorithmError> {
+    let recid =
+        RecoveryId::from_i32(recid as i32).map_err(|_| LockAlgorithmError::InvalidSignature)?;
+    let signature = RecoverableSignature::from_compact(compact_signature, recid)
+        .map_err(|_| LockAlgorithmError::InvalidSignature)?;
+    let message = secp256k1::Message::from_slice(message)
+        .map_err(|_| LockAlgorithmError::InvalidSignature)?;
+    Ok(SECP256K1.recover(&message, &signature).ok())
+}
@@ -1,26 +1,8 @@
+use anyhow::{anyhow, Result};
 use gw_common::{blake2b::new_blake2b, H256};
-use gw_types::bytes::Bytes;
-use lazy_static::lazy_static;
-use std::collections::HashMap;
-
-lazy_static! {
-    static ref SUDT_GENERATOR: Bytes = include_bytes!("../../../c/build/sudt-generator")
-        .to_vec()
-        .into();
-    // TODO FIXME implement validator
-    static ref SUDT_VALIDATOR: Bytes = include_bytes!("../../../c/build/sudt-generator")
-        .to_vec()
-        .into();
-    pub static ref SUDT_VALIDATOR_CODE_HASH: H256 = code_hash(&SUDT_VALIDATOR);
-    static ref META_CONTRACT_GENERATOR: Bytes = include_bytes!("../../../c/build/meta-contract-generator")
-        .to_vec()
-        .into();
-    // TODO FIXME implement validator
-    static ref META_CONTRACT_VALIDATOR: Bytes = include_bytes!("../../../c/build/meta-contract-generator")
-        .to_vec()
-        .into();
-    pub static ref META_CONTRACT_VALIDATOR_CODE_HASH: H256 = code_hash(&META_CONTRACT_VALIDATOR);
-}
+use gw_config::{BackendConfig, BackendType};
+use gw_types::{bytes::Bytes, prelude::*};
+use std::{collections::HashMap, fs, path::Path};
 
 fn code_hash(data: &[u8]) -> H256 {
     let mut hasher = new_blake2b();
@@ -46,38 +28,69 @@ impl Backend {
             validator_code_hash,
         }
     }
+
+    /// Load the validator and generator binaries from files,
+    /// returns an error if the validator doesn't match the expected code hash
+    pub fn from_config(config: &BackendConfig) -> Result<Backend> {
+        let validator = read_binary(&config.validator_path)?;
+        let generator = read_binary(&config.generator_path)?;
+        let backend = Backend::from_binaries(validator, generator);
+        if let Some(expected_code_hash) = config.validator_code_hash {
+            let actual_code_hash: [u8; 32] = backend.validator_code_hash.into();
+            if actual_code_hash != expected_code_hash {
+                return Err(anyhow!(
+                    "validator {} code hash mismatch, expected {} actual {}",
+                    config.validator_path.to_string_lossy(),
+                    expected_code_hash.pack(),
+                    actual_code_hash.pack()
+                ));
+            }
+        }
+        Ok(backend)
+    }
 }
 
-#[derive(Clone)]
+fn read_binary<P: AsRef<Path>>(path: P) -> Result<Bytes> {
+    let content = fs::read(path.as_ref()).map_err(|err| {
+        anyhow!(
+            "can't read backend binary {}: {}",
+            path.as_ref().to_string_lossy(),
+            err
+        )
+    })?;
+    Ok(content.into())
+}
+
+/// Backends of layer2 contracts, indexed by the validator code hash
+///
+/// No backend is builtin, the meta contract and sUDT backends are loaded from the config as others.
+#[derive(Clone, Default)]
 pub struct BackendManage {
     backends: HashMap<H256, Backend>,
 }
 
-impl Default for BackendManage {
-    fn default() -> Self {
-        let mut backend_manage = BackendManage {
-            backends: Default::default(),
-        };
-
-        // Meta contract
-        backend_manage.register_backend(Backend {
-            validator: META_CONTRACT_VALIDATOR.clone(),
-            generator: META_CONTRACT_GENERATOR.clone(),
-            validator_code_hash: META_CONTRACT_VALIDATOR_CODE_HASH.clone(),
-        });
-
-        // Simple UDT
-        backend_manage.register_backend(Backend {
-            validator: SUDT_VALIDATOR.clone(),
-            generator: SUDT_GENERATOR.clone(),
-            validator_code_hash: SUDT_VALIDATOR_CODE_HASH.clone(),
-        });
-
-        backend_manage
+impl BackendManage {
+    /// Load the configured backends, returns an error if any of them fails to load
+    /// or the meta contract and sUDT backends are missing
+    pub fn from_config(configs: &[BackendConfig]) -> Result<Self> {
+        for (backend_type, name) in &[
+            (BackendType::Meta, "meta contract"),
+            (BackendType::Sudt, "sUDT"),
+        ] {
+            if !configs
+                .iter()
+                .any(|config| &config.backend_type == backend_type)
+            {
+                return Err(anyhow!("the {} backend is missing", name));
+            }
+        }
+        let mut backend_manage = Self::default();
+        for config in configs {
+            backend_manage.register_backend(Backend::from_config(config)?);
+        }
+        Ok(backend_manage)
     }
-}
 
-impl BackendManage {
     pub fn register_backend(&mut self, backend: Backend) {
         self.backends.insert(backend.validator_code_hash, backend);
     }
@@ -81,6 +81,10 @@ pub enum TransactionError {
     State(StateError),
     #[error("Unknown backend account_id {account_id}")]
     Backend { account_id: u32 },
+    #[error("exceeded max cycles {max_cycles}")]
+    ExceededMaxCycles { max_cycles: u64 },
+    #[error("invalid withdrawal request {index}, error: {error}")]
+    Withdrawal { index: u32, error: ValidateError },
 }
 
 impl From<VMError> for TransactionError {
@@ -7,30 +7,33 @@ use crate::{
     error::LockAlgorithmError,
     traits::{CodeStore, StateExt},
 };
-use crate::{error::ValidateError, syscalls::L2Syscalls, types::RunResult};
+use crate::{
+    error::ValidateError, parallel_executor::ParallelExecution, syscalls::L2Syscalls,
+    types::RunResult,
+};
 use gw_common::{
     error::Error as StateError,
     h256_ext::H256Ext,
     state::{build_account_field_key, State, GW_ACCOUNT_NONCE},
     H256,
 };
+use gw_config::GeneratorConfig;
 use gw_types::{
+    bytes::Bytes,
     core::ScriptHashType,
     packed::{
-        BlockInfo, DepositionRequest, L2Block, RawL2Block, RawL2Transaction, StartChallenge,
-        WithdrawalRequest,
+        BlockInfo, DepositionRequest, L2Block, L2Transaction, RawL2Block, RawL2Transaction,
+        StartChallenge, WithdrawalRequest,
     },
     prelude::*,
 };
 
 use ckb_vm::{
+    instructions::cost_model::instruction_cycles,
     machine::asm::{AsmCoreMachine, AsmMachine},
-    DefaultMachineBuilder,
+    DefaultMachineBuilder, Error as VMError, SupportMachine,
 };
 
-// TODO ensure this value
-const MIN_WITHDRAWAL_CAPACITY: u64 = 100_0000_0000;
-
 pub struct StateTransitionArgs {
     pub l2block: L2Block,
     pub deposition_requests: Vec<DepositionRequest>,
@@ -43,14 +46,58 @@ pub struct StateTransitionResult {
 pub struct Generator {
     backend_manage: BackendManage,
     account_lock_manage: AccountLockManage,
+    config: GeneratorConfig,
 }
 
 impl Generator {
-    pub fn new(backend_manage: BackendManage, account_lock_manage: AccountLockManage) -> Self {
+    pub fn new(
+        backend_manage: BackendManage,
+        account_lock_manage: AccountLockManage,
+        config: GeneratorConfig,
+    ) -> Self {
         Generator {
             backend_manage,
             account_lock_manage,
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &GeneratorConfig {
+        &self.config
+    }
+
+    /// Replace backends without rebuilding the generator
+    pub fn set_backend_manage(&mut self, backend_manage: BackendManage) {
+        self.backend_manage = backend_manage;
+    }
+
+    /// Verify the signature of a layer2 tx with the lock algorithm of the sender's script
+    pub fn verify_transaction_signature<S: State + CodeStore>(
+        &self,
+        state: &S,
+        tx: &L2Transaction,
+    ) -> Result<(), Error> {
+        let sender_id: u32 = tx.raw().from_id().unpack();
+        let script_hash = state.get_script_hash(sender_id)?;
+        let script = state
+            .get_script(&script_hash)
+            .ok_or(StateError::MissingKey)?;
+        let lock_code_hash: [u8; 32] = script.code_hash().unpack();
+        let lock_algo = self
+            .account_lock_manage
+            .get_lock_algorithm(&lock_code_hash.into())
+            .ok_or(ValidateError::UnknownAccountLockScript)?;
+
+        let message = tx.hash().into();
+        let valid_signature = lock_algo.verify_signature(
+            script.args().unpack(),
+            tx.signature().as_bytes(),
+            message,
+        )?;
+        if !valid_signature {
+            return Err(LockAlgorithmError::InvalidSignature.into());
         }
+        Ok(())
     }
 
     pub fn verify_withdrawal_request<S: State + CodeStore>(
@@ -65,9 +112,9 @@ impl Generator {
         let capacity: u64 = raw.capacity().unpack();
 
         // check capacity
-        if capacity < MIN_WITHDRAWAL_CAPACITY {
+        if capacity < self.config.min_withdrawal_capacity {
             return Err(ValidateError::InsufficientCapacity {
-                expected: MIN_WITHDRAWAL_CAPACITY,
+                expected: self.config.min_withdrawal_capacity,
                 actual: capacity,
             }
             .into());
@@ -86,7 +133,7 @@ impl Generator {
         let message = raw.hash().into();
         let valid_signature = lock_algo.verify_signature(
             account_script.args().unpack(),
-            withdrawal_request.signature(),
+            withdrawal_request.signature().as_bytes(),
             message,
         )?;
 
@@ -122,44 +169,123 @@ impl Generator {
 
     /// Apply l2 state transition
     ///
+    /// Txs are speculatively executed in parallel, conflicting txs are re-executed in order.
+    ///
     /// Notice:
     /// This function do not verify the block and transactions signature.
     /// The caller is supposed to do the verification.
-    pub fn apply_state_transition<S: State + CodeStore>(
+    pub fn apply_state_transition<S: State + CodeStore + Sync>(
         &self,
         state: &mut S,
         args: StateTransitionArgs,
+    ) -> Result<StateTransitionResult, Error> {
+        self.apply_block(state, args, false, true)
+    }
+
+    /// Apply l2 state transition, txs are executed one by one in order
+    ///
+    /// It's the reference of the speculative parallel execution,
+    /// used to confirm a failed block before challenging it.
+    pub fn apply_sequential_state_transition<S: State + CodeStore + Sync>(
+        &self,
+        state: &mut S,
+        args: StateTransitionArgs,
+    ) -> Result<StateTransitionResult, Error> {
+        self.apply_block(state, args, false, false)
+    }
+
+    /// Apply a block which is accepted by layer1
+    ///
+    /// Layer1 is authoritative, a tx which is failed to execute locally is skipped instead of
+    /// failing the whole block, its receipt records the state root before the tx.
+    /// An invalid withdrawal request is skipped as well.
+    pub fn apply_accepted_state_transition<S: State + CodeStore + Sync>(
+        &self,
+        state: &mut S,
+        args: StateTransitionArgs,
+    ) -> Result<StateTransitionResult, Error> {
+        self.apply_block(state, args, true, true)
+    }
+
+    fn apply_block<S: State + CodeStore + Sync>(
+        &self,
+        state: &mut S,
+        args: StateTransitionArgs,
+        skip_failed_txs: bool,
+        parallel: bool,
     ) -> Result<StateTransitionResult, Error> {
         let raw_block = args.l2block.raw();
-        let withdrawal_requests: Vec<_> = args.l2block.withdrawal_requests().into_iter().collect();
+        let block_hash = raw_block.hash();
         // apply withdrawal to state
-        state.apply_withdrawal_requests(&withdrawal_requests)?;
+        for (index, request) in args.l2block.withdrawal_requests().into_iter().enumerate() {
+            let err = match state.apply_withdrawal_requests(std::slice::from_ref(&request)) {
+                Ok(()) => continue,
+                Err(Error::Validate(err)) => err,
+                Err(err) => return Err(err),
+            };
+            if skip_failed_txs {
+                let number: u64 = raw_block.number().unpack();
+                eprintln!(
+                    "skip withdrawal request {} of the accepted block {}, error: {}",
+                    index, number, err
+                );
+                continue;
+            }
+            // withdrawal requests are applied before txs,
+            // the block is challenged from the first tx since the state before txs is invalid
+            return Err(TransactionErrorWithContext::new(
+                build_challenge_context(0, block_hash),
+                TransactionError::Withdrawal {
+                    index: index as u32,
+                    error: err,
+                },
+            )
+            .into());
+        }
         // apply deposition to state
         state.apply_deposition_requests(&args.deposition_requests)?;
 
         // handle transactions
         let block_info = get_block_info(&raw_block);
-        let block_hash = raw_block.hash();
-        let mut receipts = Vec::with_capacity(args.l2block.transactions().len());
-        for (tx_index, tx) in args.l2block.transactions().into_iter().enumerate() {
-            let raw_tx = tx.raw();
+        let txs: Vec<_> = args.l2block.transactions().into_iter().collect();
+        let raw_txs: Vec<_> = txs.iter().map(|tx| tx.raw()).collect();
+        let mut execution = if parallel {
+            self.execute_parallel(state, &block_info, &raw_txs)
+        } else {
+            ParallelExecution::sequential()
+        };
+        let mut receipts = Vec::with_capacity(txs.len());
+        for (tx_index, (tx, raw_tx)) in txs.iter().zip(raw_txs.iter()).enumerate() {
             // check nonce
             let expected_nonce = state.get_nonce(raw_tx.from_id().unpack())?;
             let actual_nonce: u32 = raw_tx.nonce().unpack();
-            if actual_nonce != expected_nonce {
-                return Err(TransactionErrorWithContext::new(
-                    build_challenge_context(tx_index as u32, block_hash),
-                    TransactionError::Nonce {
-                        expected: expected_nonce,
-                        actual: actual_nonce,
-                    },
-                )
-                .into());
-            }
             // build call context
             // NOTICE users only allowed to send HandleMessage CallType txs
-            let run_result = match self.execute(state, &block_info, &raw_tx) {
+            let result = if actual_nonce != expected_nonce {
+                Err(TransactionError::Nonce {
+                    expected: expected_nonce,
+                    actual: actual_nonce,
+                })
+            } else {
+                execution.take_result(self, state, &block_info, tx_index, raw_tx)
+            };
+            let run_result = match result {
                 Ok(run_result) => run_result,
+                Err(err) if skip_failed_txs => {
+                    let number: u64 = raw_block.number().unpack();
+                    eprintln!(
+                        "skip tx {} of the accepted block {}, error: {}",
+                        tx_index, number, err
+                    );
+                    let compacted_post_account_root = state.calculate_compacted_account_root()?;
+                    receipts.push(TxReceipt {
+                        tx_witness_hash: tx.witness_hash().into(),
+                        compacted_post_account_root: compacted_post_account_root.into(),
+                        read_data_hashes: Vec::new(),
+                        used_cycles: 0,
+                    });
+                    continue;
+                }
                 Err(err) => {
                     return Err(TransactionErrorWithContext::new(
                         build_challenge_context(tx_index as u32, block_hash),
@@ -169,6 +295,7 @@ impl Generator {
                 }
             };
             state.apply_run_result(&run_result)?;
+            execution.commit(&run_result);
 
             let compacted_post_account_root = state.calculate_compacted_account_root()?;
             let tx_receipt = TxReceipt {
@@ -179,6 +306,7 @@ impl Generator {
                     .into_iter()
                     .map(|(hash, _)| hash.into())
                     .collect(),
+                used_cycles: run_result.used_cycles,
             };
             receipts.push(tx_receipt);
         }
@@ -214,12 +342,28 @@ impl Generator {
         state: &S,
         block_info: &BlockInfo,
         raw_tx: &RawL2Transaction,
+    ) -> Result<RunResult, TransactionError> {
+        let account_id = raw_tx.to_id().unpack();
+        let backend = self
+            .load_backend(state, account_id)?
+            .ok_or(TransactionError::Backend { account_id })?;
+        self.run_program(state, block_info, raw_tx, &backend.generator)
+    }
+
+    fn run_program<S: State + CodeStore>(
+        &self,
+        state: &S,
+        block_info: &BlockInfo,
+        raw_tx: &RawL2Transaction,
+        program: &Bytes,
     ) -> Result<RunResult, TransactionError> {
         let mut run_result = RunResult::default();
         {
-            let core_machine = Box::<AsmCoreMachine>::default();
-            let machine_builder =
-                DefaultMachineBuilder::new(core_machine).syscall(Box::new(L2Syscalls {
+            let max_cycles = self.config.max_cycles;
+            let core_machine = AsmCoreMachine::new_with_max_cycles(max_cycles);
+            let machine_builder = DefaultMachineBuilder::new(core_machine)
+                .instruction_cycle_func(Box::new(instruction_cycles))
+                .syscall(Box::new(L2Syscalls {
                     state,
                     block_info: block_info,
                     raw_tx,
@@ -227,15 +371,15 @@ impl Generator {
                     code_store: state,
                 }));
             let mut machine = AsmMachine::new(machine_builder.build(), None);
-            let account_id = raw_tx.to_id().unpack();
-            let backend = self
-                .load_backend(state, account_id)?
-                .ok_or(TransactionError::Backend { account_id })?;
-            machine.load_program(&backend.generator, &[])?;
-            let code = machine.run()?;
+            machine.load_program(program, &[])?;
+            let code = machine.run().map_err(|err| match err {
+                VMError::InvalidCycles => TransactionError::ExceededMaxCycles { max_cycles },
+                err => err.into(),
+            })?;
             if code != 0 {
                 return Err(TransactionError::InvalidExitCode(code).into());
             }
+            run_result.used_cycles = machine.machine.cycles();
         }
         // set nonce
         let sender_id: u32 = raw_tx.from_id().unpack();
@@ -255,7 +399,7 @@ impl Generator {
     }
 }
 
-fn get_block_info(l2block: &RawL2Block) -> BlockInfo {
+pub fn get_block_info(l2block: &RawL2Block) -> BlockInfo {
     BlockInfo::new_builder()
         .aggregator_id(l2block.aggregator_id())
         .number(l2block.number())
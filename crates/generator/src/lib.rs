@@ -6,6 +6,7 @@ pub mod backend_manage;
 pub mod dummy_state;
 mod error;
 pub mod generator;
+pub mod parallel_executor;
 pub mod syscalls;
 #[cfg(test)]
 mod tests;
@@ -13,6 +14,6 @@ pub mod traits;
 mod types;
 
 // re-exports
-pub use error::Error;
+pub use error::{Error, TransactionError};
 pub use generator::Generator;
 pub use types::*;
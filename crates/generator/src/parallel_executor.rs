@@ -0,0 +1,141 @@
+//! Optimistic parallel execution of layer2 transactions
+//!
+//! Txs are speculatively executed against the same state snapshot in parallel,
+//! then the caller commits results one by one in the original order.
+//! A speculative result is discarded and the tx is re-executed on the latest state
+//! if it read keys written by the committed txs.
+
+use crate::{error::TransactionError, generator::Generator, traits::CodeStore, types::RunResult};
+use gw_common::{state::State, H256};
+use gw_types::packed::{BlockInfo, RawL2Transaction};
+use std::collections::HashSet;
+
+/// Keys written by the committed txs
+#[derive(Default)]
+struct WriteSet {
+    keys: HashSet<H256>,
+    data: HashSet<H256>,
+    /// account count and scripts reads are not recorded in the run result,
+    /// so all the following txs are treated as conflicts once an account is created
+    accounts_changed: bool,
+}
+
+impl WriteSet {
+    fn record(&mut self, run_result: &RunResult) {
+        self.keys.extend(run_result.write_values.keys().copied());
+        self.data.extend(run_result.write_data.keys().copied());
+        if run_result.account_count.is_some() || !run_result.new_scripts.is_empty() {
+            self.accounts_changed = true;
+        }
+    }
+
+    fn is_conflict(&self, run_result: &RunResult) -> bool {
+        self.accounts_changed
+            || run_result
+                .read_values
+                .keys()
+                .any(|key| self.keys.contains(key))
+            || run_result
+                .read_data
+                .keys()
+                .any(|hash| self.data.contains(hash))
+    }
+}
+
+/// Speculative results of txs
+pub struct ParallelExecution {
+    results: Vec<Option<Result<RunResult, TransactionError>>>,
+    write_set: WriteSet,
+}
+
+impl ParallelExecution {
+    /// An execution without speculative results, txs are executed when they are taken
+    pub fn sequential() -> Self {
+        ParallelExecution {
+            results: Vec::new(),
+            write_set: WriteSet::default(),
+        }
+    }
+
+    /// Take the result of the tx at `index`
+    ///
+    /// `state` must be the snapshot with all the prior committed txs applied,
+    /// the tx is re-executed on it if the speculative result is failed or conflicted.
+    pub fn take_result<S: State + CodeStore>(
+        &mut self,
+        generator: &Generator,
+        state: &S,
+        block_info: &BlockInfo,
+        index: usize,
+        raw_tx: &RawL2Transaction,
+    ) -> Result<RunResult, TransactionError> {
+        match self.results.get_mut(index).and_then(Option::take) {
+            Some(Ok(run_result)) if !self.write_set.is_conflict(&run_result) => Ok(run_result),
+            _ => generator.execute(state, block_info, raw_tx),
+        }
+    }
+
+    /// Commit the result of a tx which is applied to the state
+    pub fn commit(&mut self, run_result: &RunResult) {
+        self.write_set.record(run_result);
+    }
+}
+
+impl Generator {
+    /// Speculatively execute txs against the state in parallel
+    ///
+    /// If an execution thread panics, txs of the thread are left without results
+    /// and executed sequentially when they are taken.
+    pub fn execute_parallel<S: State + CodeStore + Sync>(
+        &self,
+        state: &S,
+        block_info: &BlockInfo,
+        raw_txs: &[RawL2Transaction],
+    ) -> ParallelExecution {
+        let threads = (self.config().execution_threads as usize).max(1);
+        let mut results: Vec<Option<Result<RunResult, TransactionError>>> =
+            raw_txs.iter().map(|_| None).collect();
+        if threads == 1 || raw_txs.len() <= 1 {
+            for (index, raw_tx) in raw_txs.iter().enumerate() {
+                results[index] = Some(self.execute(state, block_info, raw_tx));
+            }
+        } else {
+            let outputs = crossbeam_utils::thread::scope(|scope| {
+                let handles: Vec<_> = (0..threads)
+                    .map(|worker| {
+                        scope.spawn(move |_| {
+                            raw_txs
+                                .iter()
+                                .enumerate()
+                                .skip(worker)
+                                .step_by(threads)
+                                .map(|(index, raw_tx)| {
+                                    (index, self.execute(state, block_info, raw_tx))
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+                let mut outputs = Vec::with_capacity(raw_txs.len());
+                for (worker, handle) in handles.into_iter().enumerate() {
+                    match handle.join() {
+                        Ok(worker_outputs) => outputs.extend(worker_outputs),
+                        Err(err) => eprintln!("execution thread {} panicked: {:?}", worker, err),
+                    }
+                }
+                outputs
+            })
+            .unwrap_or_else(|err| {
+                eprintln!("parallel execution panicked: {:?}", err);
+                Vec::new()
+            });
+            for (index, result) in outputs {
+                results[index] = Some(result);
+            }
+        }
+        ParallelExecution {
+            results,
+            write_set: WriteSet::default(),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    account_lock_manage::{
+        eth_secp256k1::EthSecp256k1, multisig::Multisig, secp256k1::Secp256k1, AccountLockManage,
+        LockAlgorithm,
+    },
+    error::LockAlgorithmError,
+};
+use gw_common::{blake2b::new_blake2b, ACCOUNT_LOCK_CODE_HASH, H256};
+use gw_config::{
+    AccountLockConfig, GeneratorConfig, LockAlgorithmType, ALWAYS_SUCCESS_LOCK_CODE_HASH,
+};
+use gw_types::bytes::Bytes;
+use secp256k1::{PublicKey, SecretKey};
+use tiny_keccak::{Hasher, Keccak};
+
+fn sign(secret_key: &SecretKey, message: &H256) -> Bytes {
+    sign_raw(secret_key, message.as_slice(), 0)
+}
+
+fn sign_raw(secret_key: &SecretKey, message: &[u8], recid_offset: u8) -> Bytes {
+    let secp = secp256k1::Secp256k1::new();
+    let msg = secp256k1::Message::from_slice(message).expect("message");
+    let (recid, data) = secp.sign_recoverable(&msg, secret_key).serialize_compact();
+    let mut buf = [0u8; 65];
+    buf[..64].copy_from_slice(&data);
+    buf[64] = recid.to_i32() as u8 + recid_offset;
+    Bytes::from(buf.to_vec())
+}
+
+fn pubkey_hash(secret_key: &SecretKey) -> Bytes {
+    let pubkey = PublicKey::from_secret_key(&secp256k1::Secp256k1::new(), secret_key);
+    let mut buf = [0u8; 32];
+    let mut hasher = new_blake2b();
+    hasher.update(&pubkey.serialize());
+    hasher.finalize(&mut buf);
+    Bytes::from(buf[..20].to_vec())
+}
+
+#[test]
+fn test_secp256k1_lock() {
+    let secret_key = SecretKey::from_slice(&[1u8; 32]).expect("secret key");
+    let message: H256 = [42u8; 32].into();
+    let signature = sign(&secret_key, &message);
+    let lock_args = pubkey_hash(&secret_key);
+
+    let lock = Secp256k1::default();
+    assert_eq!(
+        lock.verify_signature(lock_args.clone(), signature.clone(), message),
+        Ok(true)
+    );
+    // wrong message
+    assert_eq!(
+        lock.verify_signature(lock_args, signature.clone(), [43u8; 32].into()),
+        Ok(false)
+    );
+    // wrong pubkey hash
+    let other_secret_key = SecretKey::from_slice(&[2u8; 32]).expect("secret key");
+    assert_eq!(
+        lock.verify_signature(pubkey_hash(&other_secret_key), signature.clone(), message),
+        Ok(false)
+    );
+    // malformed lock args
+    assert_eq!(
+        lock.verify_signature(Bytes::from(vec![0u8; 19]), signature, message),
+        Err(LockAlgorithmError::InvalidLockArgs)
+    );
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut buf = [0u8; 32];
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    hasher.finalize(&mut buf);
+    buf
+}
+
+fn eth_personal_sign(secret_key: &SecretKey, message: &H256) -> Bytes {
+    let mut data = b"\x19Ethereum Signed Message:\n32".to_vec();
+    data.extend_from_slice(message.as_slice());
+    sign_raw(secret_key, &keccak256(&data), 27)
+}
+
+fn eth_address(secret_key: &SecretKey) -> Bytes {
+    let pubkey = PublicKey::from_secret_key(&secp256k1::Secp256k1::new(), secret_key);
+    let pubkey_hash = keccak256(&pubkey.serialize_uncompressed()[1..]);
+    Bytes::from(pubkey_hash[12..].to_vec())
+}
+
+#[test]
+fn test_eth_secp256k1_lock() {
+    let secret_key = SecretKey::from_slice(&[1u8; 32]).expect("secret key");
+    let message: H256 = [42u8; 32].into();
+    let signature = eth_personal_sign(&secret_key, &message);
+    let address = eth_address(&secret_key);
+    // the address of private key 0x0101..01
+    assert_eq!(
+        hex::encode(&address),
+        "1a642f0e3c3af545e7acbd38b07251b3990914f1"
+    );
+
+    let lock = EthSecp256k1::default();
+    assert_eq!(
+        lock.verify_signature(address.clone(), signature.clone(), message),
+        Ok(true)
+    );
+    // signature without personal message prefix
+    let raw_signature = sign(&secret_key, &message);
+    assert_eq!(
+        lock.verify_signature(address.clone(), raw_signature, message),
+        Ok(false)
+    );
+    // blake2b pubkey hash is not an Ethereum address
+    assert_eq!(
+        lock.verify_signature(pubkey_hash(&secret_key), signature.clone(), message),
+        Ok(false)
+    );
+    // malformed lock args
+    assert_eq!(
+        lock.verify_signature(Bytes::from(vec![0u8; 32]), signature, message),
+        Err(LockAlgorithmError::InvalidLockArgs)
+    );
+}
+
+#[test]
+fn test_multisig_lock() {
+    let secret_keys: Vec<_> = (1u8..=3)
+        .map(|i| SecretKey::from_slice(&[i; 32]).expect("secret key"))
+        .collect();
+    // 2 of 3
+    let mut lock_args = vec![2u8, 3u8];
+    for secret_key in &secret_keys {
+        lock_args.extend_from_slice(&pubkey_hash(secret_key));
+    }
+    let lock_args = Bytes::from(lock_args);
+    let message: H256 = [42u8; 32].into();
+    let multisig = |keys: &[&SecretKey]| -> Bytes {
+        let mut buf = Vec::new();
+        for key in keys {
+            buf.extend_from_slice(&sign(key, &message));
+        }
+        Bytes::from(buf)
+    };
+
+    let lock = Multisig::default();
+    assert_eq!(
+        lock.verify_signature(
+            lock_args.clone(),
+            multisig(&[&secret_keys[2], &secret_keys[0]]),
+            message
+        ),
+        Ok(true)
+    );
+    // a pubkey can't sign twice
+    assert_eq!(
+        lock.verify_signature(
+            lock_args.clone(),
+            multisig(&[&secret_keys[1], &secret_keys[1]]),
+            message
+        ),
+        Ok(false)
+    );
+    // signed by a key which is not in the lock args
+    let other_secret_key = SecretKey::from_slice(&[4u8; 32]).expect("secret key");
+    assert_eq!(
+        lock.verify_signature(
+            lock_args.clone(),
+            multisig(&[&secret_keys[0], &other_secret_key]),
+            message
+        ),
+        Ok(false)
+    );
+    // signatures less than the threshold
+    assert_eq!(
+        lock.verify_signature(lock_args.clone(), multisig(&[&secret_keys[0]]), message),
+        Err(LockAlgorithmError::InvalidSignature)
+    );
+    // threshold is greater than pubkeys count
+    let mut invalid_lock_args = lock_args.to_vec();
+    invalid_lock_args[0] = 4;
+    assert_eq!(
+        lock.verify_signature(
+            Bytes::from(invalid_lock_args),
+            multisig(&[&secret_keys[0], &secret_keys[1]]),
+            message
+        ),
+        Err(LockAlgorithmError::InvalidLockArgs)
+    );
+}
+
+#[test]
+fn test_account_lock_manage_from_config() {
+    let secret_key = SecretKey::from_slice(&[1u8; 32]).expect("secret key");
+    let message: H256 = [42u8; 32].into();
+    let signature = sign(&secret_key, &message);
+    let lock_args = pubkey_hash(&secret_key);
+    let verify = |manage: &AccountLockManage, code_hash: [u8; 32], lock_args: Bytes| {
+        manage
+            .get_lock_algorithm(&code_hash.into())
+            .expect("lock algorithm")
+            .verify_signature(lock_args, signature.clone(), message)
+    };
+
+    // the default config keeps the always success lock and the secp256k1 lock
+    let manage = AccountLockManage::from_config(&GeneratorConfig::default().account_locks);
+    assert_eq!(
+        verify(&manage, ALWAYS_SUCCESS_LOCK_CODE_HASH, Bytes::new()),
+        Ok(true)
+    );
+    assert_eq!(verify(&manage, ACCOUNT_LOCK_CODE_HASH, lock_args), Ok(true));
+    assert_eq!(
+        verify(&manage, ACCOUNT_LOCK_CODE_HASH, Bytes::from(vec![0u8; 20])),
+        Ok(false)
+    );
+
+    // the configured lock replaces the default one under the same code hash
+    let manage = AccountLockManage::from_config(&[AccountLockConfig {
+        code_hash: ACCOUNT_LOCK_CODE_HASH,
+        lock_algorithm: LockAlgorithmType::AlwaysSuccess,
+    }]);
+    assert_eq!(
+        verify(&manage, ACCOUNT_LOCK_CODE_HASH, Bytes::from(vec![0u8; 20])),
+        Ok(true)
+    );
+    assert!(manage
+        .get_lock_algorithm(&ALWAYS_SUCCESS_LOCK_CODE_HASH.into())
+        .is_none());
+
+    // the Ethereum compatible lock is registered under the configured code hash
+    let eth_lock_code_hash = [3u8; 32];
+    let manage = AccountLockManage::from_config(&[AccountLockConfig {
+        code_hash: eth_lock_code_hash,
+        lock_algorithm: LockAlgorithmType::EthSecp256k1,
+    }]);
+    let eth_lock = manage
+        .get_lock_algorithm(&eth_lock_code_hash.into())
+        .expect("eth lock algorithm");
+    assert_eq!(
+        eth_lock.verify_signature(
+            eth_address(&secret_key),
+            eth_personal_sign(&secret_key, &message),
+            message
+        ),
+        Ok(true)
+    );
+
+    // the multisig lock is registered under the configured code hash
+    let multisig_lock_code_hash = [4u8; 32];
+    let manage = AccountLockManage::from_config(&[AccountLockConfig {
+        code_hash: multisig_lock_code_hash,
+        lock_algorithm: LockAlgorithmType::Multisig,
+    }]);
+    let other_secret_key = SecretKey::from_slice(&[2u8; 32]).expect("secret key");
+    // 1 of 2
+    let mut multisig_lock_args = vec![1u8, 2u8];
+    multisig_lock_args.extend_from_slice(&pubkey_hash(&other_secret_key));
+    multisig_lock_args.extend_from_slice(&pubkey_hash(&secret_key));
+    assert_eq!(
+        verify(
+            &manage,
+            multisig_lock_code_hash,
+            Bytes::from(multisig_lock_args)
+        ),
+        Ok(true)
+    );
+}
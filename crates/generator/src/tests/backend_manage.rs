@@ -0,0 +1,133 @@
+use super::{
+    new_block_info, BUILD_DIR, EXAMPLES_DIR, META_CONTRACT_BIN_NAME, SUDT_BIN_NAME,
+    SUDT_PROGRAM_CODE_HASH, SUM_BIN_NAME, SUM_PROGRAM, SUM_PROGRAM_CODE_HASH,
+};
+use crate::{
+    account_lock_manage::AccountLockManage, backend_manage::BackendManage, dummy_state::DummyState,
+    error::TransactionError, traits::StateExt, Generator,
+};
+use gw_config::{BackendConfig, BackendType, GeneratorConfig};
+use gw_types::{
+    bytes::Bytes,
+    packed::{RawL2Transaction, Script},
+    prelude::*,
+};
+use std::path::PathBuf;
+
+fn backend_config(
+    dir: &str,
+    name: &str,
+    backend_type: BackendType,
+    validator_code_hash: Option<[u8; 32]>,
+) -> BackendConfig {
+    let mut path = PathBuf::new();
+    path.push(dir);
+    path.push(name);
+    BackendConfig {
+        validator_path: path.clone(),
+        generator_path: path,
+        validator_code_hash,
+        backend_type,
+    }
+}
+
+fn sum_backend_config(validator_code_hash: Option<[u8; 32]>) -> BackendConfig {
+    backend_config(
+        EXAMPLES_DIR,
+        SUM_BIN_NAME,
+        BackendType::Unknown,
+        validator_code_hash,
+    )
+}
+
+/// Configs of the meta contract and sUDT backends and the given backend
+fn backend_configs(config: BackendConfig) -> Vec<BackendConfig> {
+    vec![
+        backend_config(BUILD_DIR, META_CONTRACT_BIN_NAME, BackendType::Meta, None),
+        backend_config(BUILD_DIR, SUDT_BIN_NAME, BackendType::Sudt, None),
+        config,
+    ]
+}
+
+#[test]
+fn test_load_backends_from_config() {
+    let backend_manage = BackendManage::from_config(&backend_configs(sum_backend_config(Some(
+        *SUM_PROGRAM_CODE_HASH,
+    ))))
+    .expect("load backends");
+    let backend = backend_manage
+        .get_backend(&(*SUM_PROGRAM_CODE_HASH).into())
+        .expect("sum backend");
+    assert_eq!(backend.generator, *SUM_PROGRAM);
+    assert!(backend_manage
+        .get_backend(&(*SUDT_PROGRAM_CODE_HASH).into())
+        .is_some());
+
+    // mismatched validator code hash
+    let err = BackendManage::from_config(&backend_configs(sum_backend_config(Some([42u8; 32]))))
+        .err()
+        .expect("code hash mismatch");
+    assert!(err.to_string().contains("code hash mismatch"));
+
+    // missing binary
+    let config = BackendConfig {
+        validator_path: "not-exists".into(),
+        generator_path: "not-exists".into(),
+        validator_code_hash: None,
+        backend_type: BackendType::Unknown,
+    };
+    assert!(BackendManage::from_config(&backend_configs(config)).is_err());
+
+    // the meta contract and sUDT backends are required
+    for missing_backend_type in &[BackendType::Meta, BackendType::Sudt] {
+        let configs: Vec<_> = backend_configs(sum_backend_config(None))
+            .into_iter()
+            .filter(|config| &config.backend_type != missing_backend_type)
+            .collect();
+        let err = BackendManage::from_config(&configs)
+            .err()
+            .expect("missing backend");
+        assert!(err.to_string().contains("backend is missing"));
+    }
+}
+
+#[test]
+fn test_set_backend_manage() {
+    let mut tree = DummyState::default();
+    let contract_id = tree
+        .create_account_from_script(
+            Script::new_builder()
+                .code_hash(SUM_PROGRAM_CODE_HASH.pack())
+                .args([0u8; 20].to_vec().pack())
+                .build(),
+        )
+        .expect("create account");
+    let mut generator = Generator::new(
+        BackendManage::default(),
+        AccountLockManage::default(),
+        GeneratorConfig::default(),
+    );
+    let block_info = new_block_info(0, 1, 0);
+    let raw_tx = RawL2Transaction::new_builder()
+        .from_id(2u32.pack())
+        .to_id(contract_id.pack())
+        .args(Bytes::from(7u64.to_le_bytes().to_vec()).pack())
+        .build();
+    let err = generator
+        .execute(&tree, &block_info, &raw_tx)
+        .expect_err("unknown backend");
+    assert_eq!(
+        err,
+        TransactionError::Backend {
+            account_id: contract_id
+        }
+    );
+
+    generator.set_backend_manage(
+        BackendManage::from_config(&backend_configs(sum_backend_config(None)))
+            .expect("load backends"),
+    );
+    generator
+        .execute(&tree, &block_info, &raw_tx)
+        .expect("execute");
+}
@@ -3,10 +3,12 @@ use crate::{
     account_lock_manage::{always_success::AlwaysSuccess, AccountLockManage},
     backend_manage::{Backend, BackendManage},
     dummy_state::DummyState,
+    error::TransactionError,
     traits::StateExt,
     Generator,
 };
 use gw_common::H256;
+use gw_config::GeneratorConfig;
 use gw_types::{
     bytes::Bytes,
     packed::{RawL2Transaction, Script},
@@ -39,7 +41,11 @@ fn test_example_sum() {
         let mut account_lock_manage = AccountLockManage::default();
         account_lock_manage
             .register_lock_algorithm(H256::zero(), Box::new(AlwaysSuccess::default()));
-        let generator = Generator::new(backend_manage, account_lock_manage);
+        let generator = Generator::new(
+            backend_manage,
+            account_lock_manage,
+            GeneratorConfig::default(),
+        );
         let mut sum_value = init_value;
         for (number, add_value) in &[(1u64, 7u64), (2u64, 16u64)] {
             let block_info = new_block_info(0, *number, 0);
@@ -58,8 +64,47 @@ fn test_example_sum() {
             };
             sum_value += add_value;
             assert_eq!(return_value, sum_value);
+            assert!(run_result.used_cycles > 0);
             tree.apply_run_result(&run_result).expect("update state");
             println!("result {:?}", run_result);
         }
     }
 }
+
+#[test]
+fn test_example_sum_exceeded_max_cycles() {
+    let mut tree = DummyState::default();
+    let contract_id = tree
+        .create_account_from_script(
+            Script::new_builder()
+                .code_hash(SUM_PROGRAM_CODE_HASH.pack())
+                .args([0u8; 20].to_vec().pack())
+                .build(),
+        )
+        .expect("create account");
+
+    let mut backend_manage = BackendManage::default();
+    backend_manage.register_backend(Backend::from_binaries(
+        SUM_PROGRAM.clone(),
+        SUM_PROGRAM.clone(),
+    ));
+    let max_cycles = 100;
+    let generator = Generator::new(
+        backend_manage,
+        AccountLockManage::default(),
+        GeneratorConfig {
+            max_cycles,
+            ..Default::default()
+        },
+    );
+    let block_info = new_block_info(0, 1, 0);
+    let raw_tx = RawL2Transaction::new_builder()
+        .from_id(2u32.pack())
+        .to_id(contract_id.pack())
+        .args(Bytes::from(7u64.to_le_bytes().to_vec()).pack())
+        .build();
+    let err = generator
+        .execute(&tree, &block_info, &raw_tx)
+        .expect_err("exceeded max cycles");
+    assert_eq!(err, TransactionError::ExceededMaxCycles { max_cycles });
+}
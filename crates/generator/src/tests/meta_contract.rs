@@ -1,7 +1,6 @@
-use super::new_block_info;
+use super::{new_backend_manage, new_block_info, META_CONTRACT_PROGRAM_CODE_HASH};
 use crate::{
     account_lock_manage::AccountLockManage,
-    backend_manage::{BackendManage, META_CONTRACT_VALIDATOR_CODE_HASH},
     dummy_state::DummyState,
     error::TransactionError,
     syscalls::ERROR_DUPLICATED_SCRIPT_HASH,
@@ -11,6 +10,7 @@ use crate::{
 use core::panic;
 use gw_common::state::State;
 use gw_common::H256;
+use gw_config::GeneratorConfig;
 use gw_types::{
     packed::{BlockInfo, CreateAccount, MetaContractArgs, RawL2Transaction, Script},
     prelude::*,
@@ -28,9 +28,13 @@ fn run_contract<S: State + CodeStore>(
         .to_id(to_id.pack())
         .args(args.as_bytes().pack())
         .build();
-    let backend_manage = BackendManage::default();
+    let backend_manage = new_backend_manage();
     let account_lock_manage = AccountLockManage::default();
-    let generator = Generator::new(backend_manage, account_lock_manage);
+    let generator = Generator::new(
+        backend_manage,
+        account_lock_manage,
+        GeneratorConfig::default(),
+    );
     let run_result = generator.execute(tree, block_info, &raw_tx)?;
     tree.apply_run_result(&run_result).expect("update state");
     Ok(run_result.return_data)
@@ -43,7 +47,7 @@ fn test_meta_contract() {
     let meta_contract_id = tree
         .create_account_from_script(
             Script::new_builder()
-                .code_hash(Into::<[u8; 32]>::into(META_CONTRACT_VALIDATOR_CODE_HASH.clone()).pack())
+                .code_hash(META_CONTRACT_PROGRAM_CODE_HASH.pack())
                 .args([0u8; 32].to_vec().pack())
                 .build(),
         )
@@ -98,7 +102,7 @@ fn test_duplicated_script_hash() {
     let meta_contract_id = tree
         .create_account_from_script(
             Script::new_builder()
-                .code_hash(Into::<[u8; 32]>::into(META_CONTRACT_VALIDATOR_CODE_HASH.clone()).pack())
+                .code_hash(META_CONTRACT_PROGRAM_CODE_HASH.pack())
                 .args([0u8; 32].to_vec().pack())
                 .build(),
         )
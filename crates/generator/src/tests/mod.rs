@@ -1,32 +1,61 @@
+use crate::backend_manage::{Backend, BackendManage};
 use gw_common::blake2b::new_blake2b;
 use gw_types::{bytes::Bytes, packed::BlockInfo, prelude::*};
 use lazy_static::lazy_static;
 use std::{fs, io::Read, path::PathBuf};
 
+mod account_lock;
+mod backend_manage;
 mod examples;
 mod meta_contract;
+mod parallel_executor;
 mod sudt;
 
+const BUILD_DIR: &'static str = "../../c/build";
 const EXAMPLES_DIR: &'static str = "../../c/build/examples";
 const SUM_BIN_NAME: &'static str = "sum-generator";
+const SUDT_BIN_NAME: &'static str = "sudt-generator";
+const META_CONTRACT_BIN_NAME: &'static str = "meta-contract-generator";
 
 lazy_static! {
-    static ref SUM_PROGRAM: Bytes = {
-        let mut buf = Vec::new();
-        let mut path = PathBuf::new();
-        path.push(&EXAMPLES_DIR);
-        path.push(&SUM_BIN_NAME);
-        let mut f = fs::File::open(&path).expect("load program");
-        f.read_to_end(&mut buf).expect("read program");
-        Bytes::from(buf.to_vec())
-    };
-    static ref SUM_PROGRAM_CODE_HASH: [u8; 32] = {
-        let mut buf = [0u8; 32];
-        let mut hasher = new_blake2b();
-        hasher.update(&SUM_PROGRAM);
-        hasher.finalize(&mut buf);
-        buf
-    };
+    static ref SUM_PROGRAM: Bytes = load_program(EXAMPLES_DIR, SUM_BIN_NAME);
+    static ref SUM_PROGRAM_CODE_HASH: [u8; 32] = code_hash(&SUM_PROGRAM);
+    static ref SUDT_PROGRAM: Bytes = load_program(BUILD_DIR, SUDT_BIN_NAME);
+    static ref SUDT_PROGRAM_CODE_HASH: [u8; 32] = code_hash(&SUDT_PROGRAM);
+    static ref META_CONTRACT_PROGRAM: Bytes = load_program(BUILD_DIR, META_CONTRACT_BIN_NAME);
+    static ref META_CONTRACT_PROGRAM_CODE_HASH: [u8; 32] = code_hash(&META_CONTRACT_PROGRAM);
+}
+
+fn load_program(dir: &str, name: &str) -> Bytes {
+    let mut buf = Vec::new();
+    let mut path = PathBuf::new();
+    path.push(dir);
+    path.push(name);
+    let mut f = fs::File::open(&path).expect("load program");
+    f.read_to_end(&mut buf).expect("read program");
+    Bytes::from(buf.to_vec())
+}
+
+fn code_hash(program: &[u8]) -> [u8; 32] {
+    let mut buf = [0u8; 32];
+    let mut hasher = new_blake2b();
+    hasher.update(program);
+    hasher.finalize(&mut buf);
+    buf
+}
+
+/// Backends of the meta contract and sUDT
+pub fn new_backend_manage() -> BackendManage {
+    let mut backend_manage = BackendManage::default();
+    backend_manage.register_backend(Backend::from_binaries(
+        META_CONTRACT_PROGRAM.clone(),
+        META_CONTRACT_PROGRAM.clone(),
+    ));
+    backend_manage.register_backend(Backend::from_binaries(
+        SUDT_PROGRAM.clone(),
+        SUDT_PROGRAM.clone(),
+    ));
+    backend_manage
 }
 
 pub fn new_block_info(aggregator_id: u32, number: u64, timestamp: u64) -> BlockInfo {
@@ -0,0 +1,132 @@
+use super::{new_block_info, SUM_PROGRAM, SUM_PROGRAM_CODE_HASH};
+use crate::{
+    account_lock_manage::AccountLockManage,
+    backend_manage::{Backend, BackendManage},
+    dummy_state::DummyState,
+    generator::StateTransitionArgs,
+    traits::StateExt,
+    Generator,
+};
+use gw_common::state::State;
+use gw_config::GeneratorConfig;
+use gw_types::{
+    bytes::Bytes,
+    packed::{L2Block, L2Transaction, RawL2Block, RawL2Transaction, Script},
+    prelude::*,
+};
+
+fn new_generator(execution_threads: u32) -> Generator {
+    let mut backend_manage = BackendManage::default();
+    backend_manage.register_backend(Backend::from_binaries(
+        SUM_PROGRAM.clone(),
+        SUM_PROGRAM.clone(),
+    ));
+    Generator::new(
+        backend_manage,
+        AccountLockManage::default(),
+        GeneratorConfig {
+            execution_threads,
+            ..Default::default()
+        },
+    )
+}
+
+/// Returns the state and the id of the sum contract
+fn setup_state() -> (DummyState, u32) {
+    let mut tree = DummyState::default();
+    let contract_id = tree
+        .create_account_from_script(
+            Script::new_builder()
+                .code_hash(SUM_PROGRAM_CODE_HASH.pack())
+                .args([0u8; 20].to_vec().pack())
+                .build(),
+        )
+        .expect("create account");
+    (tree, contract_id)
+}
+
+fn sum_tx(from_id: u32, to_id: u32, nonce: u32, add_value: u64) -> RawL2Transaction {
+    RawL2Transaction::new_builder()
+        .from_id(from_id.pack())
+        .to_id(to_id.pack())
+        .nonce(nonce.pack())
+        .args(Bytes::from(add_value.to_le_bytes().to_vec()).pack())
+        .build()
+}
+
+fn return_value(return_data: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(return_data);
+    u64::from_le_bytes(buf)
+}
+
+#[test]
+fn test_conflicting_speculative_result_is_re_executed() {
+    let (mut tree, contract_id) = setup_state();
+    let generator = new_generator(4);
+    let block_info = new_block_info(0, 1, 0);
+    // both txs read and write the sum of the contract
+    let raw_txs = vec![sum_tx(2, contract_id, 0, 7), sum_tx(3, contract_id, 0, 16)];
+    let mut execution = generator.execute_parallel(&tree, &block_info, &raw_txs);
+    for (index, (raw_tx, expected_sum)) in raw_txs.iter().zip(&[7u64, 23u64]).enumerate() {
+        let sequential_result = generator
+            .execute(&tree, &block_info, raw_tx)
+            .expect("execute");
+        let run_result = execution
+            .take_result(&generator, &tree, &block_info, index, raw_tx)
+            .expect("take result");
+        assert_eq!(run_result, sequential_result);
+        assert_eq!(return_value(&run_result.return_data), *expected_sum);
+        tree.apply_run_result(&run_result).expect("update state");
+        execution.commit(&run_result);
+    }
+}
+
+#[test]
+fn test_parallel_state_transition_matches_sequential() {
+    let (_tree, contract_id) = setup_state();
+    let txs: Vec<L2Transaction> = vec![
+        sum_tx(2, contract_id, 0, 7),
+        sum_tx(3, contract_id, 0, 16),
+        sum_tx(2, contract_id, 1, 5),
+        sum_tx(4, contract_id, 0, 1),
+    ]
+    .into_iter()
+    .map(|raw_tx| L2Transaction::new_builder().raw(raw_tx).build())
+    .collect();
+    let l2block = L2Block::new_builder()
+        .raw(RawL2Block::new_builder().number(1u64.pack()).build())
+        .transactions(txs.pack())
+        .build();
+
+    let apply_block = |execution_threads: u32| {
+        let (mut tree, _contract_id) = setup_state();
+        let result = new_generator(execution_threads)
+            .apply_state_transition(
+                &mut tree,
+                StateTransitionArgs {
+                    l2block: l2block.clone(),
+                    deposition_requests: Vec::new(),
+                },
+            )
+            .expect("apply block");
+        (tree, result.receipts)
+    };
+    let (sequential_tree, sequential_receipts) = apply_block(1);
+    let (parallel_tree, parallel_receipts) = apply_block(4);
+    assert_eq!(parallel_receipts, sequential_receipts);
+    assert_eq!(
+        parallel_tree.calculate_root().expect("root"),
+        sequential_tree.calculate_root().expect("root")
+    );
+
+    // all the additions are applied to the sum
+    let run_result = new_generator(1)
+        .execute(
+            &parallel_tree,
+            &new_block_info(0, 2, 0),
+            &sum_tx(2, contract_id, 2, 0),
+        )
+        .expect("execute");
+    assert_eq!(return_value(&run_result.return_data), 29);
+}
@@ -1,9 +1,6 @@
-use super::new_block_info;
+use super::{new_backend_manage, new_block_info, SUDT_PROGRAM_CODE_HASH};
+use crate::dummy_state::DummyState;
 use crate::{account_lock_manage::AccountLockManage, Generator};
-use crate::{
-    backend_manage::{BackendManage, SUDT_VALIDATOR_CODE_HASH},
-    dummy_state::DummyState,
-};
 use crate::{
     error::TransactionError,
     traits::{CodeStore, StateExt},
@@ -11,6 +8,7 @@ use crate::{
 use core::panic;
 use gw_common::state::State;
 use gw_common::{h256_ext::H256Ext, H256};
+use gw_config::GeneratorConfig;
 use gw_types::{
     packed::{BlockInfo, RawL2Transaction, SUDTArgs, SUDTQuery, SUDTTransfer, Script},
     prelude::*,
@@ -30,9 +28,13 @@ fn run_contract<S: State + CodeStore>(
         .to_id(to_id.pack())
         .args(args.as_bytes().pack())
         .build();
-    let backend_manage = BackendManage::default();
+    let backend_manage = new_backend_manage();
     let account_lock_manage = AccountLockManage::default();
-    let generator = Generator::new(backend_manage, account_lock_manage);
+    let generator = Generator::new(
+        backend_manage,
+        account_lock_manage,
+        GeneratorConfig::default(),
+    );
     let run_result = generator.execute(tree, block_info, &raw_tx)?;
     tree.apply_run_result(&run_result).expect("update state");
     Ok(run_result.return_data)
@@ -47,7 +49,7 @@ fn test_sudt() {
     let sudt_id = tree
         .create_account_from_script(
             Script::new_builder()
-                .code_hash(Into::<[u8; 32]>::into(SUDT_VALIDATOR_CODE_HASH.clone()).pack())
+                .code_hash(SUDT_PROGRAM_CODE_HASH.pack())
                 .args([0u8; 32].to_vec().pack())
                 .build(),
         )
@@ -183,7 +185,7 @@ fn test_insufficient_balance() {
     let sudt_id = tree
         .create_account_from_script(
             Script::new_builder()
-                .code_hash(Into::<[u8; 32]>::into(SUDT_VALIDATOR_CODE_HASH.clone()).pack())
+                .code_hash(SUDT_PROGRAM_CODE_HASH.pack())
                 .args([0u8; 20].to_vec().pack())
                 .build(),
         )
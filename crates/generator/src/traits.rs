@@ -1,8 +1,12 @@
-use crate::error::Error;
+use crate::error::{Error, ValidateError};
 use crate::types::RunResult;
 use gw_common::{
-    builtins::CKB_SUDT_ACCOUNT_ID, error::Error as StateError,
-    merkle_utils::calculate_compacted_account_root, state::State, H256,
+    builtins::CKB_SUDT_ACCOUNT_ID,
+    error::Error as StateError,
+    h256_ext::H256Ext,
+    merkle_utils::calculate_compacted_account_root,
+    state::{build_account_field_key, State, GW_ACCOUNT_NONCE},
+    H256,
 };
 use gw_types::{
     bytes::Bytes,
@@ -102,7 +106,22 @@ impl<S: State + CodeStore> StateExt for S {
             // find user account
             let id = self
                 .get_account_id_by_script_hash(&account_script_hash.into())?
-                .ok_or(StateError::MissingKey)?; // find Simple UDT account
+                .ok_or(StateError::MissingKey)?;
+            // check and increase nonce, a withdrawal request can't be replayed
+            let nonce = self.get_nonce(id)?;
+            let request_nonce: u32 = raw.nonce().unpack();
+            if request_nonce != nonce {
+                return Err(ValidateError::InvalidWithdrawalNonce {
+                    expected: nonce,
+                    actual: request_nonce,
+                }
+                .into());
+            }
+            self.update_raw(
+                build_account_field_key(id, GW_ACCOUNT_NONCE),
+                H256::from_u32(nonce + 1),
+            )?;
+            // find Simple UDT account
             let capacity: u64 = raw.capacity().unpack();
             // burn CKB
             self.burn_sudt(CKB_SUDT_ACCOUNT_ID, id, capacity.into())?;
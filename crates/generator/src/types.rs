@@ -14,6 +14,7 @@ pub struct TxReceipt {
     // hash(account_root|account_count)
     pub compacted_post_account_root: H256,
     pub read_data_hashes: Vec<H256>,
+    pub used_cycles: u64,
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Default)]
@@ -26,6 +27,8 @@ pub struct RunResult {
     pub write_data: HashMap<H256, Vec<u8>>,
     // data hash -> data full size
     pub read_data: HashMap<H256, usize>,
+    // cycles consumed by the VM
+    pub used_cycles: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -86,6 +86,7 @@ pub struct TxReceipt {
     pub tx_witness_hash: H256,
     pub compacted_post_account_root: H256,
     pub read_data_hashes: Vec<H256>,
+    pub used_cycles: Uint64,
 }
 
 impl From<TxReceipt> for gw_generator::TxReceipt {
@@ -94,6 +95,7 @@ impl From<TxReceipt> for gw_generator::TxReceipt {
             tx_witness_hash,
             compacted_post_account_root,
             read_data_hashes,
+            used_cycles,
         } = json;
         let tx_witness_hash: [u8; 32] = tx_witness_hash.into();
         let compacted_post_account_root: [u8; 32] = compacted_post_account_root.into();
@@ -108,6 +110,7 @@ impl From<TxReceipt> for gw_generator::TxReceipt {
             tx_witness_hash: tx_witness_hash.into(),
             compacted_post_account_root: compacted_post_account_root.into(),
             read_data_hashes,
+            used_cycles: used_cycles.into(),
         }
     }
 }
@@ -118,6 +121,7 @@ impl From<gw_generator::TxReceipt> for TxReceipt {
             tx_witness_hash,
             compacted_post_account_root,
             read_data_hashes,
+            used_cycles,
         } = data;
         let tx_witness_hash: [u8; 32] = tx_witness_hash.into();
         let compacted_post_account_root: [u8; 32] = compacted_post_account_root.into();
@@ -132,6 +136,7 @@ impl From<gw_generator::TxReceipt> for TxReceipt {
             tx_witness_hash: tx_witness_hash.into(),
             compacted_post_account_root: compacted_post_account_root.into(),
             read_data_hashes,
+            used_cycles: used_cycles.into(),
         }
     }
 }
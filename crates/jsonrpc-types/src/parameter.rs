@@ -1,7 +1,7 @@
-use ckb_jsonrpc_types::{JsonBytes, Script as JsonScript, Uint32, Uint64};
+use ckb_jsonrpc_types::{CellOutput, JsonBytes, Script as JsonScript, Uint32, Uint64};
 use ckb_types::packed as ckb_packed;
 use ckb_types::H256;
-use gw_chain::{chain, next_block_context};
+use gw_chain::{chain, deposition, next_block_context};
 use gw_types::{core, packed, prelude::*};
 
 use serde::{Deserialize, Serialize};
@@ -88,8 +88,8 @@ impl From<NextBlockContext> for next_block_context::NextBlockContext {
 #[serde(tag = "type")]
 pub enum L1ActionContext {
     SubmitTxs {
-        /// deposition requests
-        deposition_requests: Vec<JsonBytes>,
+        /// resolved input cells of the transaction
+        input_cells: Vec<InputCell>,
     },
     Challenge {
         context: JsonBytes,
@@ -105,7 +105,7 @@ pub enum L1ActionContext {
 impl Default for L1ActionContext {
     fn default() -> Self {
         L1ActionContext::SubmitTxs {
-            deposition_requests: vec![],
+            input_cells: vec![],
         }
     }
 }
@@ -113,17 +113,8 @@ impl Default for L1ActionContext {
 impl From<L1ActionContext> for chain::L1ActionContext {
     fn from(json: L1ActionContext) -> chain::L1ActionContext {
         match json {
-            L1ActionContext::SubmitTxs {
-                deposition_requests,
-            } => chain::L1ActionContext::SubmitTxs {
-                deposition_requests: deposition_requests
-                    .into_iter()
-                    .map(|d| {
-                        let d_bytes = d.into_bytes();
-                        packed::DepositionRequest::from_slice(d_bytes.as_ref())
-                            .expect("Build packed::DepositionRequest from slice")
-                    })
-                    .collect(),
+            L1ActionContext::SubmitTxs { input_cells } => chain::L1ActionContext::SubmitTxs {
+                input_cells: input_cells.into_iter().map(|c| c.into()).collect(),
             },
             L1ActionContext::Challenge {
                 context: start_challenge,
@@ -156,6 +147,23 @@ impl From<L1ActionContext> for chain::L1ActionContext {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct InputCell {
+    pub output: CellOutput,
+    pub data: JsonBytes,
+}
+
+impl From<InputCell> for deposition::InputCell {
+    fn from(json: InputCell) -> deposition::InputCell {
+        let InputCell { output, data } = json;
+        Self {
+            output: output.into(),
+            data: data.into_bytes(),
+        }
+    }
+}
+
 /// sync method returned events
 #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
 #[serde(rename_all = "snake_case")]
@@ -274,6 +282,12 @@ pub struct Config {
     pub rpc: RPC,
     pub genesis: GenesisConfig,
     pub aggregator: Option<AggregatorConfig>,
+    /// default limits are used if it's not set
+    pub tx_pool: Option<TxPoolConfig>,
+    /// default limits are used if it's not set
+    pub generator: Option<GeneratorConfig>,
+    /// the meta contract and sUDT backends must be included
+    pub backends: Option<Vec<BackendConfig>>,
 }
 
 impl From<Config> for gw_config::Config {
@@ -287,6 +301,12 @@ impl From<Config> for gw_config::Config {
                 Some(aggregator) => Some(aggregator.into()),
                 None => None,
             },
+            tx_pool: json.tx_pool.map(Into::into).unwrap_or_default(),
+            generator: json.generator.map(Into::into).unwrap_or_default(),
+            backends: json
+                .backends
+                .map(|backends| backends.into_iter().map(Into::into).collect())
+                .unwrap_or_default(),
         }
     }
 }
@@ -301,6 +321,9 @@ impl From<gw_config::Config> for Config {
                 Some(aggregator) => Some(aggregator.into()),
                 None => None,
             },
+            tx_pool: Some(config.tx_pool.into()),
+            generator: Some(config.generator.into()),
+            backends: Some(config.backends.into_iter().map(Into::into).collect()),
         }
     }
 }
@@ -331,16 +354,232 @@ impl From<gw_config::AggregatorConfig> for AggregatorConfig {
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Default)]
 #[serde(rename_all = "snake_case")]
-pub struct SignerConfig {}
+pub struct SignerConfig {
+    pub keystore_path: String,
+    pub password: Option<String>,
+}
 
 impl From<SignerConfig> for gw_config::SignerConfig {
-    fn from(_json: SignerConfig) -> gw_config::SignerConfig {
-        Self {}
+    fn from(json: SignerConfig) -> gw_config::SignerConfig {
+        Self {
+            keystore_path: json.keystore_path.into(),
+            password: json.password,
+        }
     }
 }
 impl From<gw_config::SignerConfig> for SignerConfig {
-    fn from(_signer_config: gw_config::SignerConfig) -> SignerConfig {
-        Self {}
+    fn from(signer_config: gw_config::SignerConfig) -> SignerConfig {
+        Self {
+            keystore_path: signer_config.keystore_path.to_string_lossy().to_string(),
+            password: signer_config.password,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct TxPoolConfig {
+    pub max_packaged_txs: Uint32,
+    pub max_packaged_withdrawals: Uint32,
+    pub max_pool_txs: Uint32,
+    pub max_txs_per_sender: Uint32,
+    pub max_queued_txs_per_sender: Uint32,
+    pub max_tx_data_bytes: Uint32,
+    pub max_block_data_bytes: Uint32,
+    pub max_block_cycles: Uint64,
+    pub min_custodian_change_capacity: Uint64,
+    pub journal_path: Option<String>,
+}
+
+impl From<TxPoolConfig> for gw_config::TxPoolConfig {
+    fn from(json: TxPoolConfig) -> gw_config::TxPoolConfig {
+        Self {
+            max_packaged_txs: json.max_packaged_txs.into(),
+            max_packaged_withdrawals: json.max_packaged_withdrawals.into(),
+            max_pool_txs: json.max_pool_txs.into(),
+            max_txs_per_sender: json.max_txs_per_sender.into(),
+            max_queued_txs_per_sender: json.max_queued_txs_per_sender.into(),
+            max_tx_data_bytes: json.max_tx_data_bytes.into(),
+            max_block_data_bytes: json.max_block_data_bytes.into(),
+            max_block_cycles: json.max_block_cycles.into(),
+            min_custodian_change_capacity: json.min_custodian_change_capacity.into(),
+            journal_path: json.journal_path.map(Into::into),
+        }
+    }
+}
+impl From<gw_config::TxPoolConfig> for TxPoolConfig {
+    fn from(tx_pool_config: gw_config::TxPoolConfig) -> TxPoolConfig {
+        Self {
+            max_packaged_txs: tx_pool_config.max_packaged_txs.into(),
+            max_packaged_withdrawals: tx_pool_config.max_packaged_withdrawals.into(),
+            max_pool_txs: tx_pool_config.max_pool_txs.into(),
+            max_txs_per_sender: tx_pool_config.max_txs_per_sender.into(),
+            max_queued_txs_per_sender: tx_pool_config.max_queued_txs_per_sender.into(),
+            max_tx_data_bytes: tx_pool_config.max_tx_data_bytes.into(),
+            max_block_data_bytes: tx_pool_config.max_block_data_bytes.into(),
+            max_block_cycles: tx_pool_config.max_block_cycles.into(),
+            min_custodian_change_capacity: tx_pool_config.min_custodian_change_capacity.into(),
+            journal_path: tx_pool_config
+                .journal_path
+                .map(|path| path.to_string_lossy().to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct GeneratorConfig {
+    pub min_withdrawal_capacity: Uint64,
+    pub execution_threads: Uint32,
+    pub max_cycles: Uint64,
+    /// default account locks are used if it's not set
+    pub account_locks: Option<Vec<AccountLockConfig>>,
+}
+
+impl From<GeneratorConfig> for gw_config::GeneratorConfig {
+    fn from(json: GeneratorConfig) -> gw_config::GeneratorConfig {
+        Self {
+            min_withdrawal_capacity: json.min_withdrawal_capacity.into(),
+            execution_threads: json.execution_threads.into(),
+            max_cycles: json.max_cycles.into(),
+            account_locks: match json.account_locks {
+                Some(account_locks) => account_locks.into_iter().map(Into::into).collect(),
+                None => gw_config::GeneratorConfig::default().account_locks,
+            },
+        }
+    }
+}
+impl From<gw_config::GeneratorConfig> for GeneratorConfig {
+    fn from(generator_config: gw_config::GeneratorConfig) -> GeneratorConfig {
+        Self {
+            min_withdrawal_capacity: generator_config.min_withdrawal_capacity.into(),
+            execution_threads: generator_config.execution_threads.into(),
+            max_cycles: generator_config.max_cycles.into(),
+            account_locks: Some(
+                generator_config
+                    .account_locks
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LockAlgorithmType {
+    AlwaysSuccess,
+    Secp256k1,
+    EthSecp256k1,
+    Multisig,
+}
+
+impl From<LockAlgorithmType> for gw_config::LockAlgorithmType {
+    fn from(json: LockAlgorithmType) -> gw_config::LockAlgorithmType {
+        match json {
+            LockAlgorithmType::AlwaysSuccess => gw_config::LockAlgorithmType::AlwaysSuccess,
+            LockAlgorithmType::Secp256k1 => gw_config::LockAlgorithmType::Secp256k1,
+            LockAlgorithmType::EthSecp256k1 => gw_config::LockAlgorithmType::EthSecp256k1,
+            LockAlgorithmType::Multisig => gw_config::LockAlgorithmType::Multisig,
+        }
+    }
+}
+impl From<gw_config::LockAlgorithmType> for LockAlgorithmType {
+    fn from(lock_algorithm: gw_config::LockAlgorithmType) -> LockAlgorithmType {
+        match lock_algorithm {
+            gw_config::LockAlgorithmType::AlwaysSuccess => LockAlgorithmType::AlwaysSuccess,
+            gw_config::LockAlgorithmType::Secp256k1 => LockAlgorithmType::Secp256k1,
+            gw_config::LockAlgorithmType::EthSecp256k1 => LockAlgorithmType::EthSecp256k1,
+            gw_config::LockAlgorithmType::Multisig => LockAlgorithmType::Multisig,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct AccountLockConfig {
+    pub code_hash: H256,
+    pub lock_algorithm: LockAlgorithmType,
+}
+
+impl From<AccountLockConfig> for gw_config::AccountLockConfig {
+    fn from(json: AccountLockConfig) -> gw_config::AccountLockConfig {
+        Self {
+            code_hash: json.code_hash.0,
+            lock_algorithm: json.lock_algorithm.into(),
+        }
+    }
+}
+impl From<gw_config::AccountLockConfig> for AccountLockConfig {
+    fn from(account_lock_config: gw_config::AccountLockConfig) -> AccountLockConfig {
+        Self {
+            code_hash: H256(account_lock_config.code_hash),
+            lock_algorithm: account_lock_config.lock_algorithm.into(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct BackendConfig {
+    pub validator_path: String,
+    pub generator_path: String,
+    pub validator_code_hash: Option<H256>,
+    pub backend_type: BackendType,
+}
+
+impl From<BackendConfig> for gw_config::BackendConfig {
+    fn from(json: BackendConfig) -> gw_config::BackendConfig {
+        Self {
+            validator_path: json.validator_path.into(),
+            generator_path: json.generator_path.into(),
+            validator_code_hash: json.validator_code_hash.map(|code_hash| code_hash.0),
+            backend_type: json.backend_type.into(),
+        }
+    }
+}
+impl From<gw_config::BackendConfig> for BackendConfig {
+    fn from(backend_config: gw_config::BackendConfig) -> BackendConfig {
+        Self {
+            validator_path: backend_config.validator_path.to_string_lossy().to_string(),
+            generator_path: backend_config.generator_path.to_string_lossy().to_string(),
+            validator_code_hash: backend_config.validator_code_hash.map(H256),
+            backend_type: backend_config.backend_type.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendType {
+    Meta,
+    Sudt,
+    Unknown,
+}
+
+impl Default for BackendType {
+    fn default() -> Self {
+        BackendType::Unknown
+    }
+}
+
+impl From<BackendType> for gw_config::BackendType {
+    fn from(json: BackendType) -> gw_config::BackendType {
+        match json {
+            BackendType::Meta => gw_config::BackendType::Meta,
+            BackendType::Sudt => gw_config::BackendType::Sudt,
+            BackendType::Unknown => gw_config::BackendType::Unknown,
+        }
+    }
+}
+impl From<gw_config::BackendType> for BackendType {
+    fn from(backend_type: gw_config::BackendType) -> BackendType {
+        match backend_type {
+            gw_config::BackendType::Meta => BackendType::Meta,
+            gw_config::BackendType::Sudt => BackendType::Sudt,
+            gw_config::BackendType::Unknown => BackendType::Unknown,
+        }
     }
 }
 
@@ -391,12 +630,16 @@ impl From<gw_config::GenesisConfig> for GenesisConfig {
 #[serde(rename_all = "snake_case")]
 pub struct ChainConfig {
     pub rollup_type_script: JsonScript,
+    pub deposition_lock: JsonScript,
+    pub l1_sudt_type: JsonScript,
 }
 
 impl From<ChainConfig> for gw_config::ChainConfig {
     fn from(json: ChainConfig) -> gw_config::ChainConfig {
         Self {
             rollup_type_script: json.rollup_type_script.into(),
+            deposition_lock: json.deposition_lock.into(),
+            l1_sudt_type: json.l1_sudt_type.into(),
         }
     }
 }
@@ -404,6 +647,8 @@ impl From<gw_config::ChainConfig> for ChainConfig {
     fn from(chain_config: gw_config::ChainConfig) -> ChainConfig {
         Self {
             rollup_type_script: chain_config.rollup_type_script.into(),
+            deposition_lock: chain_config.deposition_lock.into(),
+            l1_sudt_type: chain_config.l1_sudt_type.into(),
         }
     }
 }
@@ -455,6 +700,7 @@ pub struct RunResult {
     pub new_scripts: HashMap<H256, Vec<u8>>,
     pub write_data: HashMap<H256, Vec<u8>>,
     pub read_data: HashMap<H256, Uint32>,
+    pub used_cycles: Uint64,
 }
 
 impl From<RunResult> for gw_generator::RunResult {
@@ -467,6 +713,7 @@ impl From<RunResult> for gw_generator::RunResult {
             new_scripts,
             write_data,
             read_data,
+            used_cycles,
         } = json;
         let mut to_read_values: HashMap<gw_common::H256, gw_common::H256> = HashMap::new();
         for (k, v) in read_values.iter() {
@@ -504,6 +751,7 @@ impl From<RunResult> for gw_generator::RunResult {
             new_scripts: to_new_scripts,
             write_data: to_write_data,
             read_data,
+            used_cycles: used_cycles.into(),
         }
     }
 }
@@ -518,6 +766,7 @@ impl From<gw_generator::RunResult> for RunResult {
             new_scripts,
             write_data,
             read_data,
+            used_cycles,
         } = run_result;
         let mut to_read_values: HashMap<H256, H256> = HashMap::new();
         for (k, v) in read_values.iter() {
@@ -561,6 +810,7 @@ impl From<gw_generator::RunResult> for RunResult {
             new_scripts: to_new_scripts,
             write_data: to_write_data,
             read_data,
+            used_cycles: used_cycles.into(),
         }
     }
 }
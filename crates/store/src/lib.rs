@@ -4,5 +4,5 @@ mod store_impl;
 mod wrap_store;
 
 pub use overlay::OverlayStore;
-pub use store_impl::Store;
+pub use store_impl::{Store, UnlockableWithdrawal};
 pub use wrap_store::WrapStore;
@@ -41,6 +41,10 @@ impl<S: SMTStore<H256>> OverlayStore<S> {
         }
     }
 
+    pub fn account_smt(&self) -> &SMT<OverlaySMTStore<S>> {
+        &self.tree
+    }
+
     pub fn overlay_store(&self) -> &OverlaySMTStore<S> {
         self.tree.store()
     }
@@ -50,6 +54,18 @@ impl<S: SMTStore<H256>> OverlayStore<S> {
     }
 }
 
+impl<S: SMTStore<H256> + Clone> Clone for OverlayStore<S> {
+    fn clone(&self) -> Self {
+        OverlayStore::new(
+            *self.tree.root(),
+            self.tree.store().clone(),
+            self.account_count,
+            self.scripts.clone(),
+            self.codes.clone(),
+        )
+    }
+}
+
 impl<S: SMTStore<H256>> State for OverlayStore<S> {
     fn get_raw(&self, key: &H256) -> Result<H256, Error> {
         let v = self.tree.get(&(*key).into())?;
@@ -87,6 +103,7 @@ impl<S: SMTStore<H256>> CodeStore for OverlayStore<S> {
     }
 }
 
+#[derive(Clone)]
 pub struct OverlaySMTStore<S> {
     store: S,
     branches_map: HashMap<H256, BranchNode>,
@@ -5,17 +5,22 @@ use super::wrap_store::WrapStore;
 use anyhow::{anyhow, Result};
 use gw_common::{
     error::Error,
+    h256_ext::H256Ext,
     smt::{Store as SMTStore, H256, SMT},
     state::State,
+    FINALIZE_BLOCKS,
 };
 use gw_generator::{traits::CodeStore, TxReceipt};
 use gw_types::{
     bytes::Bytes,
-    packed::{GlobalState, HeaderInfo, L2Block, L2Transaction, Script},
+    packed::{
+        DepositionRequest, GlobalState, HeaderInfo, L2Block, L2Transaction, Script,
+        WithdrawalRequest,
+    },
     prelude::*,
 };
 use parking_lot::Mutex;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 pub struct Store<S> {
@@ -24,15 +29,45 @@ pub struct Store<S> {
     // Note: The block tree can use same storage with the account tree
     // But the column must be difference, otherwise the keys may be collision with each other
     block_tree: SMT<WrapStore<S>>,
+    // reverted block hash -> H256::one()
+    reverted_block_tree: SMT<WrapStore<S>>,
     // code store
     scripts: HashMap<H256, Script>,
     codes: HashMap<H256, Bytes>,
     blocks: HashMap<H256, L2Block>,
     header_infos: HashMap<H256, HeaderInfo>,
+    deposition_requests: HashMap<H256, Vec<DepositionRequest>>,
     tip_block_hash: H256,
     tip_block_number: u64,
     tip_global_state: GlobalState,
+    last_finalized_block_number: u64,
+    // last finalized block number -> withdrawals become unlockable at this finalization step,
+    // steps without withdrawals are not recorded and the taken steps are pruned
+    unlockable_withdrawals: BTreeMap<u64, Vec<UnlockableWithdrawal>>,
     transactions: HashMap<H256, (L2Transaction, TxReceipt)>,
+    // block number -> block hash of the main chain
+    block_hashes: HashMap<u64, H256>,
+    // state journal of the block in processing
+    state_journal: Option<StateJournal>,
+    // state journals of the attached blocks, used to detach blocks
+    block_state_journals: HashMap<H256, StateJournal>,
+}
+
+/// A withdrawal request which can be unlocked via finalize
+#[derive(Debug, Clone)]
+pub struct UnlockableWithdrawal {
+    pub block_hash: H256,
+    pub block_number: u64,
+    pub request: WithdrawalRequest,
+}
+
+/// StateJournal
+/// records the account state before a block is applied,
+/// the state can be reverted by writing the previous values back.
+#[derive(Debug, Default, Clone)]
+struct StateJournal {
+    account_count: u32,
+    prev_values: HashMap<H256, H256>,
 }
 
 impl<S: SMTStore<H256>> Store<S> {
@@ -40,6 +75,7 @@ impl<S: SMTStore<H256>> Store<S> {
         account_tree: SMT<WrapStore<S>>,
         account_count: u32,
         block_tree: SMT<WrapStore<S>>,
+        reverted_block_tree: SMT<WrapStore<S>>,
         scripts: HashMap<H256, Script>,
         tip_block_hash: H256,
         tip_block_number: u64,
@@ -53,14 +89,21 @@ impl<S: SMTStore<H256>> Store<S> {
             account_tree,
             account_count,
             block_tree,
+            reverted_block_tree,
             scripts,
             codes,
             blocks,
             header_infos,
             tip_block_hash,
             tip_block_number,
+            last_finalized_block_number: tip_global_state.last_finalized_block_number().unpack(),
             tip_global_state,
+            unlockable_withdrawals: Default::default(),
             transactions,
+            deposition_requests: Default::default(),
+            block_hashes: Default::default(),
+            state_journal: None,
+            block_state_journals: Default::default(),
         }
     }
 
@@ -90,7 +133,7 @@ impl<S: SMTStore<H256>> Store<S> {
             genesis.transactions().is_empty(),
             "assume genesis has no txs"
         );
-        self.insert_block(genesis.clone(), header_info, Vec::new())?;
+        self.insert_block(genesis.clone(), header_info, Vec::new(), Vec::new())?;
         self.attach_block(genesis)?;
         self.set_tip_global_state(global_state)?;
         Ok(())
@@ -111,6 +154,36 @@ impl<S: SMTStore<H256>> Store<S> {
         ))
     }
 
+    /// Create an overlay of the account state before the block is applied
+    ///
+    /// The block must be in the main chain and not finalized.
+    pub fn new_overlay_before_block(
+        &self,
+        block_hash: &H256,
+    ) -> Result<OverlayStore<WrapStore<S>>> {
+        let block = self
+            .get_block(block_hash)?
+            .ok_or_else(|| anyhow!("can't find block"))?;
+        let block_number: u64 = block.raw().number().unpack();
+        if self.block_hashes.get(&block_number) != Some(block_hash) {
+            return Err(anyhow!("block {} is not in the main chain", block_number));
+        }
+        let mut overlay = self.new_overlay()?;
+        // revert blocks from the tip to the block
+        for number in (block_number..=self.tip_block_number).rev() {
+            let journal = self
+                .block_hashes
+                .get(&number)
+                .and_then(|hash| self.block_state_journals.get(hash))
+                .ok_or_else(|| anyhow!("can't find state journal of block {}", number))?;
+            for (k, v) in &journal.prev_values {
+                overlay.update_raw(*k, *v)?;
+            }
+            overlay.set_account_count(journal.account_count)?;
+        }
+        Ok(overlay)
+    }
+
     pub fn account_smt(&self) -> &SMT<WrapStore<S>> {
         &self.account_tree
     }
@@ -119,15 +192,22 @@ impl<S: SMTStore<H256>> Store<S> {
         &self.block_tree
     }
 
+    pub fn reverted_block_smt(&self) -> &SMT<WrapStore<S>> {
+        &self.reverted_block_tree
+    }
+
     pub fn insert_block(
         &mut self,
         block: L2Block,
         header_info: HeaderInfo,
+        deposition_requests: Vec<DepositionRequest>,
         tx_receipts: Vec<TxReceipt>,
     ) -> Result<()> {
         let block_hash = block.hash().into();
         self.blocks.insert(block_hash, block.clone());
         self.header_infos.insert(block_hash, header_info);
+        self.deposition_requests
+            .insert(block_hash, deposition_requests);
         debug_assert_eq!(block.transactions().len(), tx_receipts.len());
         for (tx, tx_receipt) in block.transactions().into_iter().zip(tx_receipts) {
             self.transactions.insert(tx.hash().into(), (tx, tx_receipt));
@@ -135,20 +215,138 @@ impl<S: SMTStore<H256>> Store<S> {
         Ok(())
     }
 
+    /// Start recording changes of the account state
+    ///
+    /// The journal is committed to the next attached block,
+    /// or discarded by `rollback_state_journal`.
+    pub fn begin_state_journal(&mut self) {
+        self.state_journal = Some(StateJournal {
+            account_count: self.account_count,
+            prev_values: Default::default(),
+        });
+    }
+
+    /// Revert the account state changes since `begin_state_journal`
+    pub fn rollback_state_journal(&mut self) -> Result<()> {
+        let journal = self
+            .state_journal
+            .take()
+            .ok_or_else(|| anyhow!("no state journal in recording"))?;
+        self.revert_state_journal(journal)
+    }
+
+    fn revert_state_journal(&mut self, journal: StateJournal) -> Result<()> {
+        let StateJournal {
+            account_count,
+            prev_values,
+        } = journal;
+        for (k, v) in prev_values {
+            self.account_tree.update(k, v)?;
+        }
+        self.account_count = account_count;
+        Ok(())
+    }
+
     /// Attach block to the rollup main chain
     pub fn attach_block(&mut self, block: L2Block) -> Result<()> {
         let raw = block.raw();
         let block_number: u64 = raw.number().unpack();
-        if block_number != 0 {
-            assert_eq!(self.tip_block_number + 1, block_number);
+        if block_number != 0 && block_number != self.tip_block_number + 1 {
+            return Err(anyhow!(
+                "block {} is not the successor of the tip {}",
+                block_number,
+                self.tip_block_number
+            ));
+        }
+        let block_hash: H256 = raw.hash().into();
+        self.block_tree.update(raw.smt_key().into(), block_hash)?;
+        self.block_hashes.insert(block_number, block_hash);
+        if let Some(journal) = self.state_journal.take() {
+            self.block_state_journals.insert(block_hash, journal);
         }
-        self.block_tree
-            .update(raw.smt_key().into(), raw.hash().into())?;
-        self.tip_block_hash = raw.hash().into();
+        // a finalized block can't be reverted, release its journal
+        if let Some(finalized_number) = block_number.checked_sub(FINALIZE_BLOCKS) {
+            if let Some(finalized_hash) = self.block_hashes.get(&finalized_number) {
+                self.block_state_journals.remove(finalized_hash);
+            }
+        }
+        self.tip_block_hash = block_hash;
         self.tip_block_number = block_number;
         Ok(())
     }
 
+    /// Detach the tip block from the rollup main chain
+    ///
+    /// The account state is reverted to the state of the parent block.
+    pub fn detach_block(&mut self, block: &L2Block) -> Result<()> {
+        let raw = block.raw();
+        let block_number: u64 = raw.number().unpack();
+        let block_hash: H256 = raw.hash().into();
+        if block_hash != self.tip_block_hash {
+            return Err(anyhow!("only the tip block can be detached"));
+        }
+        if block_number == 0 {
+            return Err(anyhow!("can't detach the genesis block"));
+        }
+        let journal = self
+            .block_state_journals
+            .remove(&block_hash)
+            .ok_or_else(|| anyhow!("can't find state journal of block {}", block_number))?;
+        self.revert_state_journal(journal)?;
+        self.block_tree.update(raw.smt_key().into(), H256::zero())?;
+        self.block_hashes.remove(&block_number);
+        let parent_number = block_number - 1;
+        self.tip_block_hash = *self
+            .block_hashes
+            .get(&parent_number)
+            .ok_or_else(|| anyhow!("can't find parent block {}", parent_number))?;
+        self.tip_block_number = parent_number;
+        Ok(())
+    }
+
+    /// Whether the state journal of the block is kept
+    ///
+    /// The journal is removed when the block is detached or finalized.
+    pub fn has_block_state_journal(&self, block_hash: &H256) -> bool {
+        self.block_state_journals.contains_key(block_hash)
+    }
+
+    /// Mark blocks as reverted
+    ///
+    /// The blocks must be detached from the main chain,
+    /// their hashes are recorded in the reverted block tree.
+    pub fn revert_blocks(&mut self, block_hashes: &[H256]) -> Result<()> {
+        for block_hash in block_hashes {
+            if let Some(block) = self.blocks.get(block_hash) {
+                let block_number: u64 = block.raw().number().unpack();
+                if self.block_hashes.get(&block_number) == Some(block_hash) {
+                    return Err(anyhow!("can't revert an attached block {}", block_number));
+                }
+            }
+            self.reverted_block_tree.update(*block_hash, H256::one())?;
+        }
+        Ok(())
+    }
+
+    /// Remove the reverted mark of blocks
+    ///
+    /// Used to rollback a revert when layer1 reorg.
+    pub fn unrevert_blocks(&mut self, block_hashes: &[H256]) -> Result<()> {
+        for block_hash in block_hashes {
+            self.reverted_block_tree.update(*block_hash, H256::zero())?;
+        }
+        Ok(())
+    }
+
+    pub fn is_block_reverted(&self, block_hash: &H256) -> Result<bool, Error> {
+        Ok(self.reverted_block_tree.get(block_hash)? == H256::one())
+    }
+
+    /// Get the block hash of the main chain by block number
+    pub fn get_block_hash_by_number(&self, number: u64) -> Result<Option<H256>, Error> {
+        Ok(self.block_hashes.get(&number).cloned())
+    }
+
     pub fn get_tip_block(&self) -> Result<Option<L2Block>, Error> {
         self.get_block(&self.tip_block_hash)
     }
@@ -158,10 +356,92 @@ impl<S: SMTStore<H256>> Store<S> {
     }
 
     pub fn set_tip_global_state(&mut self, global_state: GlobalState) -> Result<(), Error> {
+        let last_finalized_block_number: u64 = global_state.last_finalized_block_number().unpack();
+        if last_finalized_block_number > self.last_finalized_block_number {
+            self.finalize_blocks(last_finalized_block_number);
+        } else if last_finalized_block_number < self.last_finalized_block_number {
+            // the finalized boundary is moved back by a layer1 reorg
+            self.unlockable_withdrawals
+                .split_off(&(last_finalized_block_number + 1));
+            self.last_finalized_block_number = last_finalized_block_number;
+        }
         self.tip_global_state = global_state;
         Ok(())
     }
 
+    /// Move the finalized boundary forward,
+    /// withdrawals in the new finalized blocks become unlockable.
+    fn finalize_blocks(&mut self, last_finalized_block_number: u64) {
+        let mut withdrawals = Vec::new();
+        for block_number in (self.last_finalized_block_number + 1)..=last_finalized_block_number {
+            let block = match self
+                .block_hashes
+                .get(&block_number)
+                .and_then(|block_hash| self.blocks.get(block_hash))
+            {
+                Some(block) => block,
+                None => continue,
+            };
+            let block_hash: H256 = block.hash().into();
+            withdrawals.extend(block.withdrawal_requests().into_iter().map(|request| {
+                UnlockableWithdrawal {
+                    block_hash,
+                    block_number,
+                    request,
+                }
+            }));
+        }
+        if !withdrawals.is_empty() {
+            self.unlockable_withdrawals
+                .insert(last_finalized_block_number, withdrawals);
+        }
+        self.last_finalized_block_number = last_finalized_block_number;
+    }
+
+    pub fn get_last_finalized_block_number(&self) -> u64 {
+        self.last_finalized_block_number
+    }
+
+    pub fn get_last_finalized_block(&self) -> Result<Option<L2Block>, Error> {
+        match self.block_hashes.get(&self.last_finalized_block_number) {
+            Some(block_hash) => self.get_block(block_hash),
+            None => Ok(None),
+        }
+    }
+
+    /// Return true if the block is in the main chain and finalized
+    pub fn is_finalized(&self, block_hash: &H256) -> Result<bool, Error> {
+        let block = match self.blocks.get(block_hash) {
+            Some(block) => block,
+            None => return Ok(false),
+        };
+        let block_number: u64 = block.raw().number().unpack();
+        Ok(block_number <= self.last_finalized_block_number
+            && self.block_hashes.get(&block_number) == Some(block_hash))
+    }
+
+    /// Get withdrawals which become unlockable when the last finalized block number moved to `last_finalized_block_number`
+    ///
+    /// Returns None if no withdrawal become unlockable at the step or they are taken.
+    pub fn get_unlockable_withdrawals(
+        &self,
+        last_finalized_block_number: u64,
+    ) -> Result<Option<Vec<UnlockableWithdrawal>>, Error> {
+        Ok(self
+            .unlockable_withdrawals
+            .get(&last_finalized_block_number)
+            .cloned())
+    }
+
+    /// Take the unlockable withdrawals of all the finalization steps in order,
+    /// the taken withdrawals are pruned from the store.
+    pub fn take_unlockable_withdrawals(&mut self) -> Vec<UnlockableWithdrawal> {
+        std::mem::take(&mut self.unlockable_withdrawals)
+            .into_iter()
+            .flat_map(|(_number, withdrawals)| withdrawals)
+            .collect()
+    }
+
     pub fn get_block(&self, block_hash: &H256) -> Result<Option<L2Block>, Error> {
         Ok(self.blocks.get(block_hash).cloned())
     }
@@ -173,6 +453,13 @@ impl<S: SMTStore<H256>> Store<S> {
         Ok(self.header_infos.get(block_hash).cloned())
     }
 
+    pub fn get_block_deposition_requests(
+        &self,
+        block_hash: &H256,
+    ) -> Result<Option<Vec<DepositionRequest>>, Error> {
+        Ok(self.deposition_requests.get(block_hash).cloned())
+    }
+
     pub fn get_transaction(&self, tx_hash: &H256) -> Result<Option<L2Transaction>, Error> {
         Ok(self.transactions.get(tx_hash).map(|(tx, _)| tx).cloned())
     }
@@ -196,10 +483,15 @@ impl<S: SMTStore<H256> + Default> Default for Store<S> {
             H256::zero(),
             WrapStore::new(Arc::new(Mutex::new(S::default()))),
         );
+        let reverted_block_tree = SMT::new(
+            H256::zero(),
+            WrapStore::new(Arc::new(Mutex::new(S::default()))),
+        );
         Store {
             account_tree,
             account_count: 0,
             block_tree,
+            reverted_block_tree,
             scripts: Default::default(),
             codes: Default::default(),
             blocks: Default::default(),
@@ -207,7 +499,13 @@ impl<S: SMTStore<H256> + Default> Default for Store<S> {
             tip_block_hash: H256::zero(),
             tip_block_number: 0,
             tip_global_state: Default::default(),
+            last_finalized_block_number: 0,
+            unlockable_withdrawals: Default::default(),
             transactions: Default::default(),
+            deposition_requests: Default::default(),
+            block_hashes: Default::default(),
+            state_journal: None,
+            block_state_journals: Default::default(),
         }
     }
 }
@@ -218,6 +516,12 @@ impl<S: SMTStore<H256>> State for Store<S> {
         Ok(v.into())
     }
     fn update_raw(&mut self, key: H256, value: H256) -> Result<(), Error> {
+        if let Some(journal) = self.state_journal.as_mut() {
+            if !journal.prev_values.contains_key(&key) {
+                let prev_value = self.account_tree.get(&key)?;
+                journal.prev_values.insert(key, prev_value);
+            }
+        }
         self.account_tree.update(key.into(), value.into())?;
         Ok(())
     }
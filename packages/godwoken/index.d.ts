@@ -1,4 +1,4 @@
-import { HexNumber, HexString, Hash, Script } from "@ckb-lumos/base";
+import { CellOutput, HexNumber, HexString, Hash, Script } from "@ckb-lumos/base";
 export interface SyncParam {
   reverts: L1Action[];
   updates: L1Action[];
@@ -18,7 +18,12 @@ export interface NextBlockContext {
 
 export interface SubmitTxs {
   type: "submit_txs";
-  deposition_requests: HexString[]; // Vec<gw_types::packed::DepositionRequest>
+  input_cells: InputCell[]; // resolved input cells of the transaction, in the same order
+}
+
+export interface InputCell {
+  output: CellOutput;
+  data: HexString;
 }
 
 export interface StartChallenge {
@@ -74,10 +79,55 @@ export interface Config {
   rpc: RPC;
   genesis: GenesisConfig;
   aggregator?: AggregatorConfig;
+  tx_pool?: TxPoolConfig;
+  generator?: GeneratorConfig;
+  backends?: BackendConfig[];
+}
+
+export interface TxPoolConfig {
+  max_packaged_txs: HexNumber;
+  max_packaged_withdrawals: HexNumber;
+  max_pool_txs: HexNumber;
+  max_txs_per_sender: HexNumber;
+  max_queued_txs_per_sender: HexNumber;
+  max_tx_data_bytes: HexNumber;
+  max_block_data_bytes: HexNumber;
+  max_block_cycles: HexNumber;
+  min_custodian_change_capacity: HexNumber;
+  journal_path?: string;
 }
 
+export interface GeneratorConfig {
+  min_withdrawal_capacity: HexNumber;
+  execution_threads: HexNumber;
+  max_cycles: HexNumber;
+  account_locks?: AccountLockConfig[]; // default account locks are used if it's not set
+}
+
+export type LockAlgorithmType =
+  | "always_success"
+  | "secp256k1"
+  | "eth_secp256k1"
+  | "multisig";
+
+export interface AccountLockConfig {
+  code_hash: Hash;
+  lock_algorithm: LockAlgorithmType;
+}
+
+export interface BackendConfig {
+  validator_path: string;
+  generator_path: string;
+  validator_code_hash?: Hash;
+  backend_type: BackendType;
+}
+
+export type BackendType = "meta" | "sudt" | "unknown";
+
 export interface ChainConfig {
   rollup_type_script: Script;
+  deposition_lock: Script; // only code_hash and hash_type are matched
+  l1_sudt_type: Script; // only code_hash and hash_type are matched
 }
 
 export interface ConsensusConfig {
@@ -97,7 +147,10 @@ export interface AggregatorConfig {
   signer: SignerConfig;
 }
 
-export interface SignerConfig {}
+export interface SignerConfig {
+  keystore_path: string;
+  password?: string;
+}
 
 export interface RunResult {
   read_values: Record<Hash, Hash>;
@@ -106,6 +159,7 @@ export interface RunResult {
   account_count?: HexNumber;
   new_scripts: Record<Hash, HexString>;
   new_data: Record<Hash, HexString>;
+  used_cycles: HexNumber;
 }
 
 export interface BranchNode {
@@ -152,7 +206,7 @@ export class ChainService {
   produceBlock(
     produceBlockParam: ProduceBlockParam
   ): Promise<ProduceBlockResult>;
-  submitL2Transaction(l2Transaction: HexString): Promise<RunResult>;
+  submitL2Transaction(l2Transaction: HexString): Promise<RunResult | null>;
   submitWithdrawalRequest(withdrawalRequest: HexString): Promise<void>;
   execute(l2Transaction: HexString): Promise<RunResult>;
   getStorageAt(rawKey: Hash): Promise<Hash>;
@@ -160,5 +214,6 @@ export class ChainService {
   tip(): HexString; // gw_bytes::packed::L2Block
   lastSynced(): HexString; // gw_bytes::packed::HeaderInfo
   status(): Status;
+  reloadBackends(): void;
   config(): Config;
 }
@@ -65,6 +65,10 @@ class ChainService {
     return this.nativeChain.status();
   }
 
+  reloadBackends() {
+    this.nativeChain.reloadBackends();
+  }
+
   config() {
     return this.config;
   }
@@ -1,15 +1,15 @@
 use anyhow::Result;
 use gw_chain::{
     chain::{Chain, ProduceBlockParam, ProduceBlockResult, SyncEvent, SyncParam},
+    error::ChainError,
     next_block_context::NextBlockContext,
+    signer::Signer,
     tx_pool::TxPool,
 };
 use gw_common::{state::State, H256};
-use gw_config::{Config, GenesisConfig};
+use gw_config::{Config, GeneratorConfig, GenesisConfig};
 use gw_generator::{
-    account_lock_manage::{always_success::AlwaysSuccess, AccountLockManage},
-    backend_manage::BackendManage,
-    Generator,
+    account_lock_manage::AccountLockManage, backend_manage::BackendManage, Generator,
 };
 use gw_jsonrpc_types::{genesis, parameter};
 use gw_store::{
@@ -26,15 +26,13 @@ pub struct NativeChain {
     pub chain: Arc<RwLock<Chain>>,
 }
 
-fn build_generator() -> Generator {
-    let mut account_lock_manage = AccountLockManage::default();
-    let code_hash = H256::from([
-        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        0, 1,
-    ]);
-    // TODO: add a real signature verifying implementation later
-    account_lock_manage.register_lock_algorithm(code_hash, Box::new(AlwaysSuccess::default()));
-    Generator::new(BackendManage::default(), account_lock_manage)
+fn build_generator(config: &GeneratorConfig, backend_manage: BackendManage) -> Generator {
+    let account_lock_manage = AccountLockManage::from_config(&config.account_locks);
+    Generator::new(
+        backend_manage,
+        account_lock_manage,
+        config.clone(),
+    )
 }
 
 declare_types! {
@@ -49,19 +47,37 @@ declare_types! {
             let header_info = packed::HeaderInfo::from_slice(genesis_setup.header_info.into_bytes().as_ref()).expect("Constructing header info");
             let mut store = Store::default();
             store.init_genesis(genesis_with_smt, header_info).expect("Initializing store");
+            // binaries are loaded once, the chain and the tx pool share the same backends
+            let backend_manage = match BackendManage::from_config(&config.backends) {
+                Ok(backend_manage) => backend_manage,
+                Err(e) => return cx.throw_error(format!("Load backends failed: {:?}", e)),
+            };
             let tx_pool = {
                 let nb_ctx = NextBlockContext {
                     aggregator_id: 0u32,
                     timestamp: 0u64,
                 };
                 let tip = packed::L2Block::default();
-                let tx_pool = TxPool::create(
-                    store.new_overlay().expect("State new overlay"), build_generator(),
-                    &tip, nb_ctx).expect("Creating TxPool");
-                Arc::new(Mutex::new(tx_pool))
+                // custodian cells are not indexed by the native chain, the custodian liquidity isn't checked
+                let custodian_provider = None;
+                let generator = build_generator(&config.generator, backend_manage.clone());
+                TxPool::create(
+                    config.tx_pool.clone(), store.new_overlay().expect("State new overlay"),
+                    generator, &tip, nb_ctx, custodian_provider)
+                    .map(|tx_pool| Arc::new(Mutex::new(tx_pool)))
+            };
+            let tx_pool = match tx_pool {
+                Ok(tx_pool) => tx_pool,
+                Err(e) => return cx.throw_error(format!("TxPool create failed: {:?}", e)),
             };
-            let chain_result: Result<Chain> = Chain::create(
-                config.clone().chain, store, build_generator(), Arc::clone(&tx_pool));
+            let chain_result: Result<Chain> = config
+                .aggregator
+                .as_ref()
+                .map(|aggregator| Signer::from_config(&aggregator.signer))
+                .transpose()
+                .and_then(|signer| Chain::create(
+                    config.clone().chain, store, build_generator(&config.generator, backend_manage),
+                    Arc::clone(&tx_pool), signer));
             match chain_result {
                 Ok(chain) => Ok(NativeChain {
                     config: config,
@@ -137,7 +153,7 @@ declare_types! {
             let js_l2_transaction = cx.argument::<JsArrayBuffer>(0)?;
             let l2_transaction_slice = cx.borrow(&js_l2_transaction, |data| { data.as_slice::<u8>() });
             let l2_transaction = packed::L2Transaction::from_slice(l2_transaction_slice).expect("Build packed::L2Transaction from slice");
-            let run_result: Result<gw_generator::RunResult > =
+            let run_result: Result<Option<gw_generator::RunResult>> =
                 cx.borrow(&this, |data| {
                     let chain = data.chain.write().unwrap();
                     let run_result = chain.tx_pool.lock().push(l2_transaction);
@@ -145,7 +161,8 @@ declare_types! {
                 });
             match run_result {
                 Ok(run_result) => {
-                    let run_result_jsonrpc: parameter::RunResult = run_result.into();
+                    // run result is null if the tx is queued to wait for prior nonces
+                    let run_result_jsonrpc: Option<parameter::RunResult> = run_result.map(Into::into);
                     let run_result_string = serde_json::to_string(&run_result_jsonrpc).expect("Serializing RunResult");
                     Ok(cx.string(run_result_string).upcast())
                 }
@@ -241,15 +258,37 @@ declare_types! {
 
         method status(mut cx) {
             let this = cx.this();
-            let status: Status =
+            let status_result: Result<Status, ChainError> =
                 cx.borrow(&this, |data| {
                     let chain = data.chain.read().unwrap();
-                    chain.local_state.status().clone()
+                    chain.local_state.status()
                 });
+            let status = match status_result {
+                Ok(status) => status,
+                Err(e) => return cx.throw_error(format!("Chain status failed: {:?}", e)),
+            };
             let status_jsonrpc: parameter::Status= status.into();
             let status_string = serde_json::to_string(&status_jsonrpc).expect("Serializing Status");
             Ok(cx.string(status_string).upcast())
         }
+
+        method reloadBackends(mut cx) {
+            let this = cx.this();
+            // binaries are read again from the configured paths,
+            // the chain and the tx pool keep the current backends if any of them fails to load
+            let reload_result: Result<()> =
+                cx.borrow(&this, |data| {
+                    let backend_manage = BackendManage::from_config(&data.config.backends)?;
+                    let mut chain = data.chain.write().unwrap();
+                    chain.tx_pool.lock().set_backend_manage(backend_manage.clone());
+                    chain.generator.set_backend_manage(backend_manage);
+                    Ok(())
+                });
+            match reload_result {
+                Ok(()) => Ok(cx.undefined().upcast()),
+                Err(e) => cx.throw_error(format!("Chain reload backends failed: {:?}", e))
+            }
+        }
     }
 }
 
@@ -7,6 +7,14 @@ const configPath = path.join(__dirname, "config.json");
 test("Init a chain by config", (t) => {
   let rawData = fs.readFileSync(configPath);
   let config = JSON.parse(rawData);
+  config.aggregator.signer.keystore_path = path.join(
+    __dirname,
+    config.aggregator.signer.keystore_path
+  );
+  for (const backend of config.backends) {
+    backend.validator_path = path.join(__dirname, backend.validator_path);
+    backend.generator_path = path.join(__dirname, backend.generator_path);
+  }
   let chainService = new ChainService(config);
   t.pass();
 });
@@ -24,7 +24,7 @@ import { ChainService, SubmitTxs } from "@ckb-godwoken/godwoken";
 import { DeploymentConfig, schemas, types } from "@ckb-godwoken/base";
 import {
   DepositionEntry,
-  scanDepositionCellsInCommittedL2Block,
+  resolveInputCells,
   tryExtractDepositionRequest,
   RunnerConfig,
   StateValidatorLockGenerator,
@@ -158,14 +158,10 @@ export class Runner {
   }
 
   async _syncL2Block(transaction: Transaction, headerInfo: types.HeaderInfo) {
-    const depositionRequests = await scanDepositionCellsInCommittedL2Block(
-      transaction,
-      this.config,
-      this.rpc
-    );
+    const inputCells = await resolveInputCells(transaction, this.rpc);
     const context: SubmitTxs = {
       type: "submit_txs",
-      deposition_requests: depositionRequests,
+      input_cells: inputCells,
     };
     const update = {
       transaction: new Reader(
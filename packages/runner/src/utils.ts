@@ -13,7 +13,7 @@ import {
 } from "@ckb-lumos/base";
 import { TransactionSkeletonType } from "@ckb-lumos/helpers";
 import { DeploymentConfig, schemas, types } from "@ckb-godwoken/base";
-import { Config, GenesisSetup } from "@ckb-godwoken/godwoken";
+import { Config, GenesisSetup, InputCell } from "@ckb-godwoken/godwoken";
 
 const { DenormalizeScript } = denormalizers;
 const { readBigUInt128LE } = utils;
@@ -47,18 +47,17 @@ export interface RunnerConfig {
   aggregatorConfig: AggregatorConfig;
 }
 
-export async function scanDepositionCellsInCommittedL2Block(
+export async function resolveInputCells(
   l2Block: Transaction,
-  config: RunnerConfig,
   rpc: RPC
-): Promise<Array<HexString>> {
-  const results: Array<HexString> = [];
+): Promise<Array<InputCell>> {
+  const results: Array<InputCell> = [];
   for (const input of l2Block.inputs) {
     const cell = await resolveOutPoint(input.previous_output, rpc);
-    const entry = await tryExtractDepositionRequest(cell, config);
-    if (entry) {
-      results.push(entry.packedRequest);
-    }
+    results.push({
+      output: cell.cell_output,
+      data: cell.data,
+    });
   }
   return results;
 }